@@ -0,0 +1,130 @@
+use crate::{Result, utils};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long cached API responses are used without asking the server.
+pub const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A cached JSON API response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub url: String,
+    pub etag: Option<String>,
+    /// Unix timestamp of the last time the server returned or confirmed the
+    /// body.
+    pub fetched_at: u64,
+    pub body: String,
+}
+
+impl CachedResponse {
+    pub fn new(url: &str, etag: Option<String>, body: String) -> Self {
+        Self {
+            url: url.to_string(),
+            etag,
+            fetched_at: utils::unix_timestamp(),
+            body,
+        }
+    }
+
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        utils::unix_timestamp().saturating_sub(self.fetched_at) < ttl.as_secs()
+    }
+}
+
+/// On-disk cache of API responses, one file per endpoint, e.g.
+/// `<dir>/<host>/api/album/<cid>/detail.json`, with `_<port>` added to the
+/// host when the URL names a port.
+///
+/// Fresh entries are served without a request; stale ones are revalidated
+/// with `If-None-Match`. In offline mode every entry is served regardless
+/// of age and nothing is fetched from the network.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    offline: bool,
+}
+
+impl ResponseCache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: CACHE_TTL,
+            offline: false,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    pub async fn get(&self, url: &str) -> Result<Option<CachedResponse>> {
+        let Some(path) = self.path(url) else {
+            return Ok(None);
+        };
+        if !utils::file_exists(&path) {
+            return Ok(None);
+        }
+
+        let content = tokio::fs::read(&path).await?;
+        match serde_json::from_slice(&content) {
+            Ok(entry) => Ok(Some(entry)),
+            Err(e) => {
+                log::warn!("Ignoring unreadable cache entry {}: {}", path.display(), e);
+                Ok(None)
+            }
+        }
+    }
+
+    pub async fn put(&self, entry: &CachedResponse) -> Result<()> {
+        let Some(path) = self.path(&entry.url) else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            utils::ensure_dir_exists(parent).await?;
+        }
+
+        let temp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&temp_path, serde_json::to_vec_pretty(entry)?).await?;
+        tokio::fs::rename(&temp_path, &path).await?;
+        Ok(())
+    }
+
+    fn path(&self, url: &str) -> Option<PathBuf> {
+        let url = url::Url::parse(url).ok()?;
+        let host = match url.port() {
+            Some(port) => format!("{}_{}", url.host_str()?, port),
+            None => url.host_str()?.to_string(),
+        };
+        let mut path = self.dir.join(utils::sanitize_filename(&host));
+        for segment in url.path_segments()? {
+            if segment.is_empty() || segment == ".." {
+                continue;
+            }
+            path.push(utils::sanitize_filename(segment));
+        }
+        // Appended rather than set, as cids may contain dots.
+        let mut path = path.into_os_string();
+        path.push(".json");
+        Some(path.into())
+    }
+}
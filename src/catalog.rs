@@ -0,0 +1,298 @@
+use crate::{
+    Result,
+    models::{Album, Song},
+    snapshot::CatalogSnapshot,
+    source::CatalogSource,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Minimum similarity for a typo-tolerant match, from 0 to 1.
+const FUZZY_THRESHOLD: f64 = 0.75;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultKind {
+    Album,
+    Song,
+}
+
+impl ResultKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResultKind::Album => "album",
+            ResultKind::Song => "song",
+        }
+    }
+}
+
+/// Which part of an album or song matched best.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchField {
+    Name,
+    Artist,
+    Intro,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub kind: ResultKind,
+    pub cid: String,
+    pub name: String,
+    /// The album a song belongs to; `None` for albums.
+    pub album_cid: Option<String>,
+    pub artists: Vec<String>,
+    pub field: MatchField,
+    pub score: f64,
+}
+
+/// Albums with their songs, searchable in memory.
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    albums: Vec<Album>,
+}
+
+impl Catalog {
+    pub fn new(albums: Vec<Album>) -> Self {
+        Self { albums }
+    }
+
+    /// Builds the catalog from the album and song listings of `source`.
+    /// Listings are cheap but may lack details such as album intros; load a
+    /// [`CatalogSnapshot`] to search those too.
+    pub async fn fetch<S: CatalogSource>(source: &S) -> Result<Self> {
+        let mut albums = source.get_albums().await?;
+        let mut songs_by_album: HashMap<String, Vec<Song>> = HashMap::new();
+        for song in source.get_songs().await? {
+            if let Some(album_id) = song.album_cid.clone() {
+                songs_by_album.entry(album_id).or_default().push(song);
+            }
+        }
+
+        for album in &mut albums {
+            if album.songs.is_none() {
+                album.songs = songs_by_album.remove(&album.cid);
+            }
+        }
+        Ok(Self::new(albums))
+    }
+
+    pub fn albums(&self) -> &[Album] {
+        &self.albums
+    }
+
+    /// Searches album names, song names, album intros and artists.
+    ///
+    /// Matching ignores case, full-width/half-width forms and spacing, so
+    /// `ＭＳＲ` finds `msr` and `塞壬 唱片` finds `塞壬唱片`. Every word of
+    /// the query has to match; words that don't appear verbatim may still
+    /// match with a typo or as an in-order subsequence, at a lower score.
+    /// Results are ranked best first.
+    pub fn search(&self, query: &str) -> Vec<SearchResult> {
+        let query = Query::new(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut results = Vec::new();
+        for album in &self.albums {
+            let artists = album.get_artistes();
+            if let Some((field, score)) = query.score(&album.name, &artists, album.intro.as_deref())
+            {
+                results.push(SearchResult {
+                    kind: ResultKind::Album,
+                    cid: album.cid.clone(),
+                    name: album.name.clone(),
+                    album_cid: None,
+                    artists,
+                    field,
+                    score,
+                });
+            }
+
+            for song in album.songs.iter().flatten() {
+                let artists = song.get_artists();
+                if let Some((field, score)) = query.score(&song.name, &artists, None) {
+                    results.push(SearchResult {
+                        kind: ResultKind::Song,
+                        cid: song.cid.clone(),
+                        name: song.name.clone(),
+                        album_cid: Some(album.cid.clone()),
+                        artists,
+                        field,
+                        score,
+                    });
+                }
+            }
+        }
+
+        results.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        results
+    }
+}
+
+impl From<CatalogSnapshot> for Catalog {
+    fn from(snapshot: CatalogSnapshot) -> Self {
+        Self::new(snapshot.albums)
+    }
+}
+
+struct Query {
+    /// The whole query, normalised.
+    full: Vec<char>,
+    words: Vec<Vec<char>>,
+}
+
+impl Query {
+    fn new(query: &str) -> Self {
+        let words: Vec<Vec<char>> = query
+            .split_whitespace()
+            .map(normalize)
+            .filter(|word| !word.is_empty())
+            .collect();
+        Self {
+            full: words.concat(),
+            words,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Best matching field and score, or `None` unless every word matches
+    /// one of the fields.
+    fn score(
+        &self,
+        name: &str,
+        artists: &[String],
+        intro: Option<&str>,
+    ) -> Option<(MatchField, f64)> {
+        let mut fields = vec![(MatchField::Name, normalize(name), 1.0)];
+        fields.extend(
+            artists
+                .iter()
+                .map(|artist| (MatchField::Artist, normalize(artist), 0.8)),
+        );
+        if let Some(intro) = intro {
+            fields.push((MatchField::Intro, normalize(intro), 0.3));
+        }
+
+        let mut total = 0.0;
+        let mut best_field = (MatchField::Name, 0.0);
+        for word in &self.words {
+            let (field, score) = fields
+                .iter()
+                .map(|(field, text, weight)| (*field, match_score(word, text) * weight))
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))?;
+            if score <= 0.0 {
+                return None;
+            }
+            total += score;
+            if score > best_field.1 {
+                best_field = (field, score);
+            }
+        }
+
+        // Favour results where the query matches as a whole, e.g. a full
+        // title rather than words scattered across fields.
+        if self.words.len() > 1 {
+            total += fields
+                .iter()
+                .map(|(_, text, weight)| match_score(&self.full, text) * weight)
+                .fold(0.0, f64::max);
+        }
+
+        Some((best_field.0, total))
+    }
+}
+
+/// Lowercases, folds full-width forms to ASCII and drops whitespace and
+/// punctuation, leaving letters, digits and CJK characters.
+fn normalize(text: &str) -> Vec<char> {
+    text.chars()
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            _ => c,
+        })
+        .flat_map(char::to_lowercase)
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// How well `word` matches `text`: exact, prefix, substring, typo-tolerant
+/// and subsequence matches score from highest to lowest; 0 means no match.
+fn match_score(word: &[char], text: &[char]) -> f64 {
+    if word.is_empty() || text.is_empty() {
+        return 0.0;
+    }
+    if word == text {
+        return 100.0;
+    }
+    if text.starts_with(word) {
+        return 80.0;
+    }
+    if text.windows(word.len()).any(|window| window == word) {
+        return 60.0;
+    }
+
+    // Typos are only meaningful for words of a few characters; a single CJK
+    // character off is usually a different word.
+    if word.len() >= 4 {
+        let similarity = best_window_similarity(word, text);
+        if similarity >= FUZZY_THRESHOLD {
+            return 50.0 * similarity;
+        }
+    }
+
+    // A subsequence spread over a long text is almost always a coincidence.
+    if word.len() >= 2 && text.len() <= word.len() * 4 && is_subsequence(word, text) {
+        return 20.0 * word.len() as f64 / text.len() as f64;
+    }
+    0.0
+}
+
+/// Highest `1 - distance / len` between `word` and any slice of `text` of
+/// roughly the same length.
+fn best_window_similarity(word: &[char], text: &[char]) -> f64 {
+    let mut best = 0.0;
+    for len in word.len().saturating_sub(1)..=word.len() + 1 {
+        if len == 0 || len > text.len() {
+            continue;
+        }
+        for window in text.windows(len) {
+            let distance = levenshtein(word, window);
+            let similarity = 1.0 - distance as f64 / word.len().max(len) as f64;
+            if similarity > best {
+                best = similarity;
+            }
+        }
+    }
+    best
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+fn is_subsequence(word: &[char], text: &[char]) -> bool {
+    let mut text = text.iter();
+    word.iter().all(|c| text.any(|t| t == c))
+}
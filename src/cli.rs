@@ -0,0 +1,375 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use msr_downloader::{
+    Filter, LyricsFormat, Pattern, ResponseCache, RetryPolicy, filter::FilterRules,
+    library::STATE_DIR,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, Parser)]
+#[command(name = "msr-downloader", version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Args)]
+pub struct GlobalArgs {
+    /// Directory the library is saved into
+    #[arg(
+        short,
+        long,
+        global = true,
+        value_name = "DIR",
+        default_value = "./Monster Siren Records"
+    )]
+    pub output: PathBuf,
+
+    /// Number of tracks downloaded at the same time within an album
+    #[arg(
+        short = 'j',
+        long,
+        global = true,
+        value_name = "N",
+        default_value_t = 5
+    )]
+    pub concurrency: usize,
+
+    /// Number of albums processed at the same time
+    #[arg(long, global = true, value_name = "N", default_value_t = 1)]
+    pub album_concurrency: usize,
+
+    /// Skip downloading lyrics
+    #[arg(long, global = true)]
+    pub no_lyrics: bool,
+
+    /// Skip downloading album covers
+    #[arg(long, global = true)]
+    pub no_covers: bool,
+
+    /// Also download music videos and their posters
+    #[arg(long, global = true)]
+    pub mvs: bool,
+
+    /// Number of music videos downloaded at the same time within an album
+    #[arg(long, global = true, value_name = "N", default_value_t = 1)]
+    pub mv_concurrency: usize,
+
+    /// Skip writing tags to downloaded tracks
+    #[arg(long, global = true)]
+    pub no_tags: bool,
+
+    /// Replace existing tags instead of merging the tool's fields into them
+    #[arg(long, global = true, conflicts_with = "no_tags")]
+    pub force_retag: bool,
+
+    /// Abort the run on the first album-level failure instead of skipping
+    /// the album
+    #[arg(long, global = true)]
+    pub fail_fast: bool,
+
+    /// Site root to fetch the catalog from, e.g. a local mirror
+    #[arg(long, global = true, value_name = "URL")]
+    pub base_url: Option<String>,
+
+    /// Extra header sent with every request, as "Name: value" (repeatable)
+    #[arg(long = "header", global = true, value_name = "HEADER")]
+    pub headers: Vec<String>,
+
+    /// User agent sent with every request
+    #[arg(long, global = true, value_name = "AGENT")]
+    pub user_agent: Option<String>,
+
+    /// Request timeout
+    #[arg(long, global = true, value_name = "SECS", default_value_t = 1200)]
+    pub timeout: u64,
+
+    /// Attempts per request before giving up, including the first one
+    #[arg(long, global = true, value_name = "N", default_value_t = 4)]
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, doubled on every further attempt
+    #[arg(long, global = true, value_name = "MS", default_value_t = 500)]
+    pub retry_delay: u64,
+
+    /// Upper bound for the delay between retries
+    #[arg(long, global = true, value_name = "MS", default_value_t = 30_000)]
+    pub max_retry_delay: u64,
+
+    /// Use exact backoff delays instead of randomising them
+    #[arg(long, global = true)]
+    pub no_jitter: bool,
+
+    /// Directory for cached API responses [default: <OUTPUT>/.msr-downloader/cache]
+    #[arg(long, global = true, value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// How long cached album and song details are used before revalidating
+    /// them. The album and song listings are always revalidated
+    #[arg(long, global = true, value_name = "SECS", default_value_t = 3600)]
+    pub cache_ttl: u64,
+
+    /// Always query the API instead of using cached responses
+    #[arg(long, global = true, conflicts_with = "offline")]
+    pub no_cache: bool,
+
+    /// Serve API responses only from the cache and never touch the network
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Config file [default: ./msr-downloader.toml, if present]
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Write a JSON report of the run, including every failure, to this file
+    #[arg(long, global = true, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+
+    /// Increase log verbosity (-v, -vv, -vvv)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only log errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+}
+
+impl GlobalArgs {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts.max(1),
+            initial_backoff: Duration::from_millis(self.retry_delay),
+            max_backoff: Duration::from_millis(self.max_retry_delay),
+            jitter: !self.no_jitter,
+        }
+    }
+
+    /// The API response cache, or `None` with `--no-cache`.
+    pub fn response_cache(&self) -> Option<ResponseCache> {
+        if self.no_cache {
+            return None;
+        }
+        let dir = self
+            .cache_dir
+            .clone()
+            .unwrap_or_else(|| self.output.join(STATE_DIR).join("cache"));
+        Some(
+            ResponseCache::new(dir)
+                .with_ttl(Duration::from_secs(self.cache_ttl))
+                .with_offline(self.offline),
+        )
+    }
+
+    pub fn log_level(&self) -> log::LevelFilter {
+        if self.quiet {
+            return log::LevelFilter::Error;
+        }
+        match self.verbose {
+            0 => log::LevelFilter::Warn,
+            1 => log::LevelFilter::Info,
+            2 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Download the whole library, or only the given albums and songs
+    Download {
+        /// Only download the album with this cid (repeatable)
+        #[arg(long = "album", value_name = "CID")]
+        albums: Vec<String>,
+
+        /// Only download the song with this cid (repeatable)
+        #[arg(long = "song", value_name = "CID")]
+        songs: Vec<String>,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+
+        /// Print every action the download would take without writing
+        /// anything
+        #[arg(long, conflicts_with = "songs")]
+        dry_run: bool,
+
+        /// Print the dry-run plan as JSON
+        #[arg(long, requires = "dry_run")]
+        json: bool,
+    },
+    /// List every album in the catalog
+    ListAlbums,
+    /// List every song in the catalog
+    ListSongs,
+    /// Show details for an album or song
+    Info {
+        /// Album or song cid
+        cid: String,
+    },
+    /// Fetch only albums and songs that are new or changed since the last run
+    Sync {
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Re-run only the items that failed in an earlier run
+    RetryFailed {
+        /// JSON report written by an earlier run with --report
+        report: PathBuf,
+    },
+    /// Search album and song names, album intros and artists
+    Search {
+        /// Words to search for
+        #[arg(required = true)]
+        query: Vec<String>,
+
+        /// Search a snapshot saved with `snapshot` instead of the live catalog
+        #[arg(long, value_name = "FILE")]
+        snapshot: Option<PathBuf>,
+
+        /// Only show albums or only songs
+        #[arg(long, value_enum)]
+        kind: Option<SearchKind>,
+
+        /// Maximum number of results
+        #[arg(long, value_name = "N", default_value_t = 20)]
+        limit: usize,
+
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Save the full catalog, with every song's details, as a JSON snapshot
+    Snapshot {
+        /// File the snapshot is written to
+        file: PathBuf,
+
+        /// Number of API requests made at the same time
+        #[arg(long, value_name = "N", default_value_t = 4)]
+        max_requests: usize,
+
+        /// Treat FILE as a directory and also save every file the catalog
+        /// references, making a snapshot that can stand in for the site
+        #[arg(long)]
+        with_files: bool,
+    },
+    /// Compare two catalog snapshots
+    Diff {
+        /// The older snapshot
+        old: PathBuf,
+
+        /// The newer snapshot
+        new: PathBuf,
+
+        /// Print the differences as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Convert the library's .lrc files to other formats, next to the
+    /// originals
+    ConvertLyrics {
+        /// Format to convert to (repeatable)
+        #[arg(long = "format", value_enum, required = true)]
+        formats: Vec<LyricsFormatArg>,
+
+        /// Replace converted files that already exist
+        #[arg(long)]
+        overwrite: bool,
+
+        /// Directory to search for .lrc files [default: the library]
+        dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SearchKind {
+    Album,
+    Song,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum LyricsFormatArg {
+    Txt,
+    Srt,
+    Vtt,
+    Ttml,
+}
+
+impl From<LyricsFormatArg> for LyricsFormat {
+    fn from(format: LyricsFormatArg) -> Self {
+        match format {
+            LyricsFormatArg::Txt => LyricsFormat::Text,
+            LyricsFormatArg::Srt => LyricsFormat::Srt,
+            LyricsFormatArg::Vtt => LyricsFormat::WebVtt,
+            LyricsFormatArg::Ttml => LyricsFormat::Ttml,
+        }
+    }
+}
+
+/// Include/exclude rules added to those from the config file. Patterns are
+/// case-insensitive globs, or regular expressions when prefixed with `re:`.
+#[derive(Debug, Args)]
+pub struct FilterArgs {
+    /// Only download the album with this cid (repeatable)
+    #[arg(long, value_name = "CID")]
+    pub include_album: Vec<String>,
+
+    /// Skip the album with this cid (repeatable)
+    #[arg(long, value_name = "CID")]
+    pub exclude_album: Vec<String>,
+
+    /// Only download albums whose name matches (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    pub include_album_name: Vec<Pattern>,
+
+    /// Skip albums whose name matches (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude_album_name: Vec<Pattern>,
+
+    /// Only download albums with this `belong` value, e.g. arknights (repeatable)
+    #[arg(long, value_name = "VALUE")]
+    pub include_belong: Vec<String>,
+
+    /// Skip albums with this `belong` value (repeatable)
+    #[arg(long, value_name = "VALUE")]
+    pub exclude_belong: Vec<String>,
+
+    /// Only download songs by a matching artist (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    pub include_artist: Vec<Pattern>,
+
+    /// Skip songs by a matching artist (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude_artist: Vec<Pattern>,
+
+    /// Only download songs whose name matches (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    pub include_song: Vec<Pattern>,
+
+    /// Skip songs whose name matches (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude_song: Vec<Pattern>,
+}
+
+impl FilterArgs {
+    pub fn filter(&self) -> Filter {
+        Filter {
+            include: FilterRules {
+                albums: self.include_album.clone(),
+                album_names: self.include_album_name.clone(),
+                belongs: self.include_belong.clone(),
+                artists: self.include_artist.clone(),
+                song_names: self.include_song.clone(),
+            },
+            exclude: FilterRules {
+                albums: self.exclude_album.clone(),
+                album_names: self.exclude_album_name.clone(),
+                belongs: self.exclude_belong.clone(),
+                artists: self.exclude_artist.clone(),
+                song_names: self.exclude_song.clone(),
+            },
+        }
+    }
+}
@@ -1,51 +1,169 @@
-use crate::{Error, Result, models::*};
+use crate::{
+    Error, Result,
+    cache::{CachedResponse, ResponseCache},
+    models::*,
+    retry::RetryPolicy,
+    source::{CatalogSource, FileResponse, ResumeFrom},
+    utils,
+};
+use futures::StreamExt;
 use reqwest::Client;
+use reqwest::header::{
+    ACCEPT, ACCEPT_LANGUAGE, ETAG, HeaderMap, HeaderName, HeaderValue, IF_NONE_MATCH, REFERER,
+};
+use serde::de::DeserializeOwned;
 use std::time::Duration;
 
 const BASE_URL: &str = "https://monster-siren.hypergryph.com";
 const USER_AGENT: &str = "msr-downloader/1.0.0";
 
+const TIMEOUT: Duration = Duration::from_secs(1200);
+
 pub struct MonsterSirenClient {
     client: Client,
     base_url: String,
+    retry_policy: RetryPolicy,
+    cache: Option<ResponseCache>,
 }
 
-impl MonsterSirenClient {
-    pub fn new(version: Option<&str>) -> Result<Self> {
-        let user_agent = match version {
-            Some(v) => format!("msr-downloader/{}", v),
-            None => USER_AGENT.to_string(),
-        };
+/// Configures a [`MonsterSirenClient`], e.g. to point it at a local stand-in
+/// server or a caching mirror instead of the official site.
+pub struct MonsterSirenClientBuilder {
+    base_url: String,
+    user_agent: String,
+    timeout: Duration,
+    headers: Vec<(String, String)>,
+    retry_policy: RetryPolicy,
+    cache: Option<ResponseCache>,
+}
+
+impl MonsterSirenClientBuilder {
+    pub fn new() -> Self {
+        Self {
+            base_url: BASE_URL.to_string(),
+            user_agent: USER_AGENT.to_string(),
+            timeout: TIMEOUT,
+            headers: Vec::new(),
+            retry_policy: RetryPolicy::default(),
+            cache: None,
+        }
+    }
+
+    /// Site root the `/api/...` endpoints are resolved against. The `Referer`
+    /// header follows it unless set explicitly.
+    pub fn base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn user_agent<S: Into<String>>(mut self, user_agent: S) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Adds a header sent with every request, replacing a default header of
+    /// the same name.
+    pub fn header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Caches JSON API responses on disk. File downloads are never cached.
+    pub fn cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn build(self) -> Result<MonsterSirenClient> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
+        headers.insert(
+            ACCEPT_LANGUAGE,
+            HeaderValue::from_static("zh-CN,zh;q=0.9,ja;q=0.8,en;q=0.7,en-GB;q=0.6,en-US;q=0.5"),
+        );
+        headers.insert(REFERER, parse_header_value(&format!("{}/", self.base_url))?);
+
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| Error::InvalidData(format!("Invalid header name {}: {}", name, e)))?;
+            headers.insert(name, parse_header_value(value)?);
+        }
 
         let client = Client::builder()
-            .timeout(Duration::from_secs(1200))
-            .user_agent(user_agent)
-            .default_headers({
-                let mut headers = reqwest::header::HeaderMap::new();
-                headers.insert("Accept", "*/*".parse().unwrap());
-                headers.insert(
-                    "Accept-Language",
-                    "zh-CN,zh;q=0.9,ja;q=0.8,en;q=0.7,en-GB;q=0.6,en-US;q=0.5"
-                        .parse()
-                        .unwrap(),
-                );
-                headers.insert(
-                    "Referer",
-                    "https://monster-siren.hypergryph.com/".parse().unwrap(),
-                );
-                headers
-            })
+            .timeout(self.timeout)
+            .user_agent(self.user_agent)
+            .default_headers(headers)
             .build()?;
 
-        Ok(Self {
+        Ok(MonsterSirenClient {
             client,
-            base_url: BASE_URL.to_string(),
+            base_url: self.base_url,
+            retry_policy: self.retry_policy,
+            cache: self.cache,
         })
     }
+}
+
+impl Default for MonsterSirenClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_header_value(value: &str) -> Result<HeaderValue> {
+    HeaderValue::from_str(value)
+        .map_err(|e| Error::InvalidData(format!("Invalid header value {}: {}", value, e)))
+}
+
+impl MonsterSirenClient {
+    pub fn new(version: Option<&str>) -> Result<Self> {
+        let mut builder = Self::builder();
+        if let Some(v) = version {
+            builder = builder.user_agent(format!("msr-downloader/{}", v));
+        }
+        builder.build()
+    }
+
+    pub fn builder() -> MonsterSirenClientBuilder {
+        MonsterSirenClientBuilder::new()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    pub fn cache(&self) -> Option<&ResponseCache> {
+        self.cache.as_ref()
+    }
+
+    /// Whether the client only serves cached responses and refuses to touch
+    /// the network.
+    pub fn is_offline(&self) -> bool {
+        self.cache.as_ref().is_some_and(ResponseCache::is_offline)
+    }
 
     pub async fn get_songs(&self) -> Result<(Vec<Song>, String)> {
         let url = format!("{}/api/songs", self.base_url);
-        let response: SongsResponse = self.client.get(&url).send().await?.json().await?;
+        let response: SongsResponse = self.get_listing_json(&url).await?;
 
         if response.code != 0 {
             return Err(Error::Api {
@@ -61,7 +179,7 @@ impl MonsterSirenClient {
 
     pub async fn get_song(&self, song_id: &str) -> Result<Option<Song>> {
         let url = format!("{}/api/song/{}", self.base_url, song_id);
-        let response: SongResponse = self.client.get(&url).send().await?.json().await?;
+        let response: SongResponse = self.get_json(&url).await?;
 
         if response.code != 0 {
             return Err(Error::Api {
@@ -74,7 +192,7 @@ impl MonsterSirenClient {
 
     pub async fn get_albums(&self) -> Result<Vec<Album>> {
         let url = format!("{}/api/albums", self.base_url);
-        let response: AlbumsResponse = self.client.get(&url).send().await?.json().await?;
+        let response: AlbumsResponse = self.get_listing_json(&url).await?;
 
         if response.code != 0 {
             return Err(Error::Api {
@@ -87,7 +205,7 @@ impl MonsterSirenClient {
 
     pub async fn get_album(&self, album_id: &str) -> Result<Option<Album>> {
         let url = format!("{}/api/album/{}/data", self.base_url, album_id);
-        let response: AlbumResponse = self.client.get(&url).send().await?.json().await?;
+        let response: AlbumResponse = self.get_json(&url).await?;
 
         if response.code != 0 {
             return Err(Error::Api {
@@ -100,7 +218,7 @@ impl MonsterSirenClient {
 
     pub async fn get_album_with_songs(&self, album_id: &str) -> Result<Option<Album>> {
         let url = format!("{}/api/album/{}/detail", self.base_url, album_id);
-        let response: AlbumResponse = self.client.get(&url).send().await?.json().await?;
+        let response: AlbumResponse = self.get_json(&url).await?;
 
         if response.code != 0 {
             return Err(Error::Api {
@@ -112,16 +230,252 @@ impl MonsterSirenClient {
     }
 
     pub async fn download_file(&self, url: &str) -> Result<reqwest::Response> {
-        let response = self.client.get(url).send().await?;
-
-        if !response.status().is_success() {
-            return Err(Error::Download(format!(
-                "Failed to download from {}: HTTP {}",
-                url,
-                response.status()
-            )));
+        self.check_online(url)?;
+        self.retry_policy
+            .run(|| async { check_status(url, self.client.get(url).send().await?) })
+            .await
+    }
+
+    /// Size of `url` as reported by the `Content-Length` of a `HEAD`
+    /// request.
+    pub async fn file_size(&self, url: &str) -> Result<Option<u64>> {
+        self.check_online(url)?;
+        let response = self
+            .retry_policy
+            .run(|| async { check_status(url, self.client.head(url).send().await?) })
+            .await?;
+        Ok(
+            utils::header_value(&response, reqwest::header::CONTENT_LENGTH)
+                .and_then(|length| length.parse().ok()),
+        )
+    }
+
+    /// Requests the rest of `url` starting at byte `offset`. The range is only
+    /// honoured while `validator` (an ETag or Last-Modified value) still
+    /// matches; otherwise the server answers with the full resource. A
+    /// range the server cannot satisfy falls back to a full download.
+    pub async fn download_file_range(
+        &self,
+        url: &str,
+        offset: u64,
+        validator: &str,
+    ) -> Result<reqwest::Response> {
+        self.check_online(url)?;
+        let response = self
+            .retry_policy
+            .run(|| async {
+                let response = self
+                    .client
+                    .get(url)
+                    .header(reqwest::header::RANGE, format!("bytes={}-", offset))
+                    .header(reqwest::header::IF_RANGE, validator)
+                    .send()
+                    .await?;
+
+                if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+                    return Ok(None);
+                }
+                check_status(url, response).map(Some)
+            })
+            .await?;
+
+        match response {
+            Some(response) => Ok(response),
+            None => self.download_file(url).await,
+        }
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        self.get_json_with(url, false).await
+    }
+
+    /// Like [`get_json`](Self::get_json), but a cached response is always
+    /// revalidated first, so the album and song listings pick up new
+    /// releases however long the cache TTL is.
+    async fn get_listing_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        self.get_json_with(url, true).await
+    }
+
+    async fn get_json_with<T: DeserializeOwned>(&self, url: &str, revalidate: bool) -> Result<T> {
+        let body = match &self.cache {
+            Some(cache) => self.get_cached_text(cache, url, revalidate).await?,
+            None => match self.fetch_text(url, None).await? {
+                Some((body, _)) => body,
+                None => return Err(Error::InvalidData(format!("Unexpected 304 from {}", url))),
+            },
+        };
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Serves `url` from the cache while the entry is fresh (or always, when
+    /// offline), otherwise revalidates it with the stored `ETag`. With
+    /// `revalidate`, entries are only served without asking when offline.
+    /// Only successful API responses (`code == 0`) are stored.
+    async fn get_cached_text(
+        &self,
+        cache: &ResponseCache,
+        url: &str,
+        revalidate: bool,
+    ) -> Result<String> {
+        let cached = cache.get(url).await?;
+        if let Some(entry) = &cached
+            && (cache.is_offline() || (!revalidate && entry.is_fresh(cache.ttl())))
+        {
+            return Ok(entry.body.clone());
         }
+        self.check_online(url)?;
+
+        let etag = cached.as_ref().and_then(|entry| entry.etag.as_deref());
+        let entry = match self.fetch_text(url, etag).await? {
+            Some((body, etag)) => CachedResponse::new(url, etag, body),
+            None => {
+                let mut entry = cached
+                    .ok_or_else(|| Error::InvalidData(format!("Unexpected 304 from {}", url)))?;
+                entry.fetched_at = utils::unix_timestamp();
+                entry
+            }
+        };
+
+        if is_api_success(&entry.body) {
+            cache.put(&entry).await?;
+        }
+        Ok(entry.body)
+    }
+
+    /// Fetches `url` as text along with its `ETag`. When `etag` is given the
+    /// request is conditional and `None` means the server answered
+    /// `304 Not Modified`.
+    async fn fetch_text(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+    ) -> Result<Option<(String, Option<String>)>> {
+        self.retry_policy
+            .run(|| async {
+                let mut request = self.client.get(url);
+                if let Some(etag) = etag {
+                    request = request.header(IF_NONE_MATCH, etag);
+                }
+
+                let response = request.send().await?;
+                if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+                    return Ok(None);
+                }
+                let response = check_status(url, response)?;
+                let etag = utils::header_value(&response, ETAG);
+                Ok(Some((response.text().await?, etag)))
+            })
+            .await
+    }
+
+    fn check_online(&self, url: &str) -> Result<()> {
+        if self.is_offline() {
+            return Err(Error::Offline {
+                url: url.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
 
-        Ok(response)
+fn is_api_success(body: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|value| value.get("code")?.as_i64())
+        == Some(0)
+}
+
+/// Turns a non-success response into [`Error::HttpStatus`], keeping any
+/// `Retry-After` delay the server asked for.
+fn check_status(url: &str, response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let retry_after = utils::header_value(&response, reqwest::header::RETRY_AFTER)
+        .and_then(|value| parse_retry_after(&value));
+
+    Err(Error::HttpStatus {
+        url: url.to_string(),
+        status: status.as_u16(),
+        retry_after,
+    })
+}
+
+/// Delay requested by a `Retry-After` value, given either in seconds or as
+/// an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = utils::parse_http_date(value)?;
+    Some(Duration::from_secs(
+        date.saturating_sub(utils::unix_timestamp()),
+    ))
+}
+
+impl CatalogSource for MonsterSirenClient {
+    async fn get_albums(&self) -> Result<Vec<Album>> {
+        MonsterSirenClient::get_albums(self).await
+    }
+
+    async fn get_album_with_songs(&self, album_id: &str) -> Result<Option<Album>> {
+        MonsterSirenClient::get_album_with_songs(self, album_id).await
+    }
+
+    async fn get_song(&self, song_id: &str) -> Result<Option<Song>> {
+        MonsterSirenClient::get_song(self, song_id).await
     }
+
+    async fn get_songs(&self) -> Result<Vec<Song>> {
+        let (songs, _) = MonsterSirenClient::get_songs(self).await?;
+        Ok(songs)
+    }
+
+    async fn fetch_file(&self, url: &str, resume: Option<ResumeFrom<'_>>) -> Result<FileResponse> {
+        let response = match resume {
+            Some(resume) => {
+                self.download_file_range(url, resume.offset, resume.validator)
+                    .await?
+            }
+            None => self.download_file(url).await?,
+        };
+
+        let partial_from = if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+            Some(content_range_start(&response).ok_or_else(|| {
+                Error::Download(format!("Partial response without a Content-Range: {}", url))
+            })?)
+        } else {
+            None
+        };
+
+        Ok(FileResponse {
+            partial_from,
+            etag: utils::header_value(&response, reqwest::header::ETAG),
+            last_modified: utils::header_value(&response, reqwest::header::LAST_MODIFIED),
+            content_length: response.content_length(),
+            body: response
+                .bytes_stream()
+                .map(|chunk| chunk.map_err(Error::from))
+                .boxed(),
+        })
+    }
+
+    async fn file_size(&self, url: &str) -> Result<Option<u64>> {
+        MonsterSirenClient::file_size(self, url).await
+    }
+}
+
+/// Start offset of a `Content-Range: bytes <start>-<end>/<total>` header.
+fn content_range_start(response: &reqwest::Response) -> Option<u64> {
+    let content_range = utils::header_value(response, reqwest::header::CONTENT_RANGE)?;
+    content_range
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
 }
@@ -0,0 +1,46 @@
+use crate::{
+    Error, Result,
+    filter::Filter,
+    metadata::{GenreMap, TagPolicies},
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Config file read from the working directory unless another one is given.
+pub const CONFIG_FILE: &str = "msr-downloader.toml";
+
+/// Settings read from a TOML config file.
+///
+/// ```toml
+/// [filter.include]
+/// belongs = ["arknights"]
+///
+/// [filter.exclude]
+/// album_names = ["*Instrumental*", "re:^OST"]
+///
+/// [tags]
+/// comment = "only_if_empty"
+///
+/// [genres.belongs.arknights]
+/// genre = "Game Soundtrack"
+/// grouping = "Arknights"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub filter: Filter,
+    /// When each tag field is written; see [`TagPolicies`].
+    pub tags: TagPolicies,
+    /// Genre and grouping tags by album `belong` value; see [`GenreMap`].
+    pub genres: GenreMap,
+}
+
+impl Config {
+    pub async fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = tokio::fs::read_to_string(path).await?;
+        toml::from_str(&content).map_err(|e| {
+            Error::InvalidData(format!("Invalid config file {}: {}", path.display(), e))
+        })
+    }
+}
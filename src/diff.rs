@@ -0,0 +1,165 @@
+use crate::{
+    models::{Album, Song},
+    snapshot::CatalogSnapshot,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// An album or song identified by cid and name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatalogItem {
+    pub cid: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SongRename {
+    pub cid: String,
+    pub album_cid: String,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+/// Changed artists of an album (`song_cid` is `None`) or a song.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtistChange {
+    pub album_cid: String,
+    pub song_cid: Option<String>,
+    pub name: String,
+    pub old_artists: Vec<String>,
+    pub new_artists: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceUrlChange {
+    pub album_cid: String,
+    pub song_cid: String,
+    pub name: String,
+    pub old_url: Option<String>,
+    pub new_url: Option<String>,
+}
+
+/// Differences between two catalog snapshots. Songs are matched by cid
+/// across the whole catalog, so a song moved to another album only shows
+/// up if something else about it changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatalogDiff {
+    pub added_albums: Vec<CatalogItem>,
+    pub removed_albums: Vec<CatalogItem>,
+    pub added_songs: Vec<CatalogItem>,
+    pub removed_songs: Vec<CatalogItem>,
+    pub renamed_songs: Vec<SongRename>,
+    pub changed_artists: Vec<ArtistChange>,
+    pub changed_source_urls: Vec<SourceUrlChange>,
+}
+
+impl CatalogDiff {
+    pub fn new(old: &CatalogSnapshot, new: &CatalogSnapshot) -> Self {
+        let old_albums: HashMap<&str, &Album> = old
+            .albums
+            .iter()
+            .map(|album| (album.cid.as_str(), album))
+            .collect();
+        let new_albums: HashMap<&str, &Album> = new
+            .albums
+            .iter()
+            .map(|album| (album.cid.as_str(), album))
+            .collect();
+        let old_songs = songs_by_cid(old);
+        let new_songs = songs_by_cid(new);
+
+        let mut diff = CatalogDiff::default();
+
+        for album in &new.albums {
+            match old_albums.get(album.cid.as_str()) {
+                None => diff.added_albums.push(item(&album.cid, &album.name)),
+                Some(old_album) => {
+                    if old_album.get_artistes() != album.get_artistes() {
+                        diff.changed_artists.push(ArtistChange {
+                            album_cid: album.cid.clone(),
+                            song_cid: None,
+                            name: album.name.clone(),
+                            old_artists: old_album.get_artistes(),
+                            new_artists: album.get_artistes(),
+                        });
+                    }
+                }
+            }
+        }
+
+        diff.removed_albums = old
+            .albums
+            .iter()
+            .filter(|album| !new_albums.contains_key(album.cid.as_str()))
+            .map(|album| item(&album.cid, &album.name))
+            .collect();
+
+        for (album, song) in new.albums.iter().flat_map(album_songs) {
+            let Some(old_song) = old_songs.get(song.cid.as_str()) else {
+                diff.added_songs.push(item(&song.cid, &song.name));
+                continue;
+            };
+
+            if old_song.name != song.name {
+                diff.renamed_songs.push(SongRename {
+                    cid: song.cid.clone(),
+                    album_cid: album.cid.clone(),
+                    old_name: old_song.name.clone(),
+                    new_name: song.name.clone(),
+                });
+            }
+            if old_song.get_artists() != song.get_artists() {
+                diff.changed_artists.push(ArtistChange {
+                    album_cid: album.cid.clone(),
+                    song_cid: Some(song.cid.clone()),
+                    name: song.name.clone(),
+                    old_artists: old_song.get_artists(),
+                    new_artists: song.get_artists(),
+                });
+            }
+            if old_song.source_url != song.source_url {
+                diff.changed_source_urls.push(SourceUrlChange {
+                    album_cid: album.cid.clone(),
+                    song_cid: song.cid.clone(),
+                    name: song.name.clone(),
+                    old_url: old_song.source_url.clone(),
+                    new_url: song.source_url.clone(),
+                });
+            }
+        }
+
+        diff.removed_songs = old
+            .albums
+            .iter()
+            .flat_map(album_songs)
+            .filter(|(_, song)| !new_songs.contains_key(song.cid.as_str()))
+            .map(|(_, song)| item(&song.cid, &song.name))
+            .collect();
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == CatalogDiff::default()
+    }
+}
+
+fn item(cid: &str, name: &str) -> CatalogItem {
+    CatalogItem {
+        cid: cid.to_string(),
+        name: name.to_string(),
+    }
+}
+
+fn album_songs(album: &Album) -> impl Iterator<Item = (&Album, &Song)> {
+    album.songs.iter().flatten().map(move |song| (album, song))
+}
+
+fn songs_by_cid(snapshot: &CatalogSnapshot) -> HashMap<&str, &Song> {
+    snapshot
+        .albums
+        .iter()
+        .flat_map(album_songs)
+        .map(|(_, song)| (song.cid.as_str(), song))
+        .collect()
+}
@@ -1,118 +1,1187 @@
 use crate::{
-    Result,
+    Error, Result,
     client::MonsterSirenClient,
-    metadata::MetadataWriter,
+    filter::Filter,
+    library::AlbumIndex,
+    lyrics::Lyrics,
+    manifest::{FileKind, Manifest, ManifestEntry},
+    metadata::{GenreMap, MetadataWriter, TagPolicies},
     models::{Album, Song},
+    plan::{Plan, PlannedAction},
     progress::ProgressTracker,
+    report::{DownloadSummary, Failure, FailureStage},
+    retry::RetryPolicy,
+    source::{CatalogSource, ResumeFrom},
+    sync::{PlannedAlbum, SyncPlan, SyncReport},
     utils,
 };
-use futures::stream::{self, StreamExt};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Mutex;
 
 const SAVE_DIR: &str = "./Monster Siren Records";
 const MAX_CONCURRENT_DOWNLOADS: usize = 5;
+const MAX_CONCURRENT_ALBUMS: usize = 1;
+const MAX_CONCURRENT_MV_DOWNLOADS: usize = 1;
 
-pub struct Downloader {
-    client: MonsterSirenClient,
-    progress: ProgressTracker,
-    metadata_writer: MetadataWriter,
-    save_path: PathBuf,
+/// Settings controlling what a [`Downloader`] fetches and where it saves it.
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    pub save_path: PathBuf,
+    /// Number of tracks downloaded at the same time within an album.
+    pub max_concurrent_downloads: usize,
+    /// Number of albums processed at the same time.
+    pub max_concurrent_albums: usize,
+    pub download_lyrics: bool,
+    pub download_covers: bool,
+    /// Download music videos and their posters next to the tracks.
+    pub download_mvs: bool,
+    /// Number of music videos downloaded at the same time within an album.
+    /// Kept separate from `max_concurrent_downloads` as videos are large.
+    pub max_concurrent_mv_downloads: usize,
+    pub write_metadata: bool,
+    /// Which of the tool's tag fields are written into existing tags.
+    pub tag_policies: TagPolicies,
+    /// Genre and grouping tags by album `belong` value.
+    pub genres: GenreMap,
+    /// Replace the existing tags rather than merging into them.
+    pub force_retag: bool,
+    pub error_policy: ErrorPolicy,
+    /// Retries for file transfers interrupted mid-stream. Retries of the
+    /// requests themselves are up to the catalog source.
+    pub retry_policy: RetryPolicy,
+    /// Albums and songs to download. Album rules are checked against the
+    /// catalog listing before any details are fetched.
+    pub filter: Filter,
+}
+
+/// What happens when an album-level step (details, info, covers) fails.
+/// Track, lyric and tagging failures never abort a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Abort the run on the first album-level failure.
+    FailFast,
+    /// Record the failure, skip what cannot be done for that album and move
+    /// on to the next one.
+    #[default]
+    Continue,
 }
 
-impl Downloader {
-    pub fn new(client: MonsterSirenClient) -> Self {
+impl Default for DownloadOptions {
+    fn default() -> Self {
         Self {
-            client,
-            progress: ProgressTracker::new(),
-            metadata_writer: MetadataWriter::new(),
             save_path: PathBuf::from(SAVE_DIR),
+            max_concurrent_downloads: MAX_CONCURRENT_DOWNLOADS,
+            max_concurrent_albums: MAX_CONCURRENT_ALBUMS,
+            download_lyrics: true,
+            download_covers: true,
+            download_mvs: false,
+            max_concurrent_mv_downloads: MAX_CONCURRENT_MV_DOWNLOADS,
+            write_metadata: true,
+            tag_policies: TagPolicies::default(),
+            genres: GenreMap::default(),
+            force_retag: false,
+            error_policy: ErrorPolicy::default(),
+            retry_policy: RetryPolicy::default(),
+            filter: Filter::default(),
+        }
+    }
+}
+
+pub struct DownloaderBuilder<S = MonsterSirenClient> {
+    source: S,
+    options: DownloadOptions,
+}
+
+impl<S: CatalogSource> DownloaderBuilder<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            options: DownloadOptions::default(),
+        }
+    }
+
+    pub fn save_path<P: Into<PathBuf>>(mut self, save_path: P) -> Self {
+        self.options.save_path = save_path.into();
+        self
+    }
+
+    pub fn max_concurrent_downloads(mut self, max: usize) -> Self {
+        self.options.max_concurrent_downloads = max.max(1);
+        self
+    }
+
+    pub fn max_concurrent_albums(mut self, max: usize) -> Self {
+        self.options.max_concurrent_albums = max.max(1);
+        self
+    }
+
+    pub fn download_lyrics(mut self, enabled: bool) -> Self {
+        self.options.download_lyrics = enabled;
+        self
+    }
+
+    pub fn download_covers(mut self, enabled: bool) -> Self {
+        self.options.download_covers = enabled;
+        self
+    }
+
+    pub fn download_mvs(mut self, enabled: bool) -> Self {
+        self.options.download_mvs = enabled;
+        self
+    }
+
+    pub fn max_concurrent_mv_downloads(mut self, max: usize) -> Self {
+        self.options.max_concurrent_mv_downloads = max.max(1);
+        self
+    }
+
+    pub fn write_metadata(mut self, enabled: bool) -> Self {
+        self.options.write_metadata = enabled;
+        self
+    }
+
+    pub fn tag_policies(mut self, policies: TagPolicies) -> Self {
+        self.options.tag_policies = policies;
+        self
+    }
+
+    pub fn genres(mut self, genres: GenreMap) -> Self {
+        self.options.genres = genres;
+        self
+    }
+
+    pub fn force_retag(mut self, enabled: bool) -> Self {
+        self.options.force_retag = enabled;
+        self
+    }
+
+    pub fn error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.options.error_policy = error_policy;
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.options.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.options.filter = filter;
+        self
+    }
+
+    /// Replaces all options at once, applying the same limits as the
+    /// individual setters.
+    pub fn options(mut self, options: DownloadOptions) -> Self {
+        let DownloadOptions {
+            max_concurrent_downloads,
+            max_concurrent_albums,
+            max_concurrent_mv_downloads,
+            ..
+        } = options;
+        self.options = options;
+        self.max_concurrent_downloads(max_concurrent_downloads)
+            .max_concurrent_albums(max_concurrent_albums)
+            .max_concurrent_mv_downloads(max_concurrent_mv_downloads)
+    }
+
+    pub fn build(self) -> Downloader<S> {
+        Downloader {
+            source: self.source,
+            progress: ProgressTracker::with_max_download_bars(
+                self.options.max_concurrent_downloads,
+            ),
+            metadata_writer: MetadataWriter::new()
+                .policies(self.options.tag_policies.clone())
+                .genres(self.options.genres.clone())
+                .force_retag(self.options.force_retag),
+            manifest: Mutex::new(Manifest::default()),
+            options: self.options,
         }
     }
+}
 
-    pub async fn download_all_tracks(&self) -> Result<()> {
-        utils::ensure_dir_exists(&self.save_path).await?;
+/// Downloads albums and songs from a [`CatalogSource`] into the library.
+pub struct Downloader<S = MonsterSirenClient> {
+    source: S,
+    progress: ProgressTracker,
+    metadata_writer: MetadataWriter,
+    manifest: Mutex<Manifest>,
+    options: DownloadOptions,
+}
 
-        let albums = self.client.get_albums().await?;
-        let total_albums = albums.len();
+/// Outcome of fetching an album's details.
+enum FetchedAlbum {
+    /// The album with its song details and the songs to download, `None`
+    /// meaning all of them.
+    Ready(Box<Album>, Option<HashSet<String>>),
+    /// The details were missing; already recorded as a failure.
+    Missing,
+    /// Excluded by the filter once its details were known.
+    Filtered,
+}
+
+/// A file of a song being replaced by sync, moved aside until the new
+/// files are downloaded.
+struct SetAsideFile {
+    entry: ManifestEntry,
+    moved_to: PathBuf,
+}
+
+/// Deletes the set-aside files of the songs that were replaced, and moves
+/// the others back unless a new file took their place.
+async fn settle_set_aside_files(
+    manifest: &mut Manifest,
+    songs: &[Song],
+    set_aside: Vec<SetAsideFile>,
+) -> Result<()> {
+    let replaced: HashSet<&str> = songs
+        .iter()
+        .filter(|song| {
+            manifest.song(&song.cid).is_some_and(|record| {
+                !record.incomplete
+                    && record.source_url == song.source_url
+                    && record.lyric_url == song.lyric_url
+            })
+        })
+        .map(|song| song.cid.as_str())
+        .collect();
+
+    for file in set_aside {
+        let path = manifest.root().join(&file.entry.path);
+        let song_replaced = file
+            .entry
+            .song_cid
+            .as_deref()
+            .is_some_and(|song_id| replaced.contains(song_id));
+
+        if song_replaced || utils::file_exists(&path) {
+            tokio::fs::remove_file(&file.moved_to).await?;
+        } else {
+            tokio::fs::rename(&file.moved_to, &path).await?;
+            manifest.record(file.entry);
+        }
+    }
+
+    Ok(())
+}
+
+/// File name of a track's audio, e.g. `01.Song Name.wav`.
+fn track_file_name(track_no: usize, song: &Song, source_url: &str) -> String {
+    let ext = utils::get_file_extension(source_url).unwrap_or_else(|| ".mp3".to_string());
+    format!("{:02}.{}{}", track_no, song.sanitized_name(), ext)
+}
+
+fn lyrics_file_name(track_no: usize, song: &Song) -> String {
+    format!("{:02}.{}.lrc", track_no, song.sanitized_name())
+}
+
+/// File name of a track's music video, e.g. `01.Song Name.mp4`.
+fn mv_file_name(track_no: usize, song: &Song, mv_url: &str) -> String {
+    let ext = utils::get_file_extension(mv_url).unwrap_or_else(|| ".mp4".to_string());
+    format!("{:02}.{}{}", track_no, song.sanitized_name(), ext)
+}
+
+/// File name of a music video's poster, e.g. `01.Song Name-poster.jpg`.
+fn mv_poster_file_name(track_no: usize, song: &Song, mv_cover_url: &str) -> String {
+    let ext = utils::get_file_extension(mv_cover_url).unwrap_or_else(|| ".jpg".to_string());
+    format!("{:02}.{}-poster{}", track_no, song.sanitized_name(), ext)
+}
+
+/// File name of a cover, e.g. `Album Cover.jpg`.
+fn cover_file_name(stem: &str, cover_url: &str) -> String {
+    let ext = utils::get_file_extension(cover_url).unwrap_or_else(|| ".jpg".to_string());
+    format!("{}{}", stem, ext)
+}
+
+/// Where a downloaded file comes from, recorded in the manifest.
+#[derive(Clone, Copy)]
+struct FileOrigin<'a> {
+    album_cid: &'a str,
+    song_cid: Option<&'a str>,
+    kind: FileKind,
+}
+
+impl<S: CatalogSource> Downloader<S> {
+    pub fn new(source: S) -> Self {
+        Self::builder(source).build()
+    }
+
+    pub fn builder(source: S) -> DownloaderBuilder<S> {
+        DownloaderBuilder::new(source)
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn options(&self) -> &DownloadOptions {
+        &self.options
+    }
+
+    pub async fn download_all_tracks(&self) -> Result<DownloadSummary> {
+        utils::ensure_dir_exists(&self.options.save_path).await?;
+        self.load_manifest().await?;
+
+        let albums = self.source.get_albums().await?;
+        // Numbers follow the whole catalog so that changing the filter never
+        // renumbers folders.
+        let mut summary = DownloadSummary::default();
+        let album_index = self.update_album_index(&albums, &mut summary).await?;
+
+        let planned: Vec<_> = self
+            .filter_albums(albums)
+            .into_iter()
+            .map(|album| PlannedAlbum { album, songs: None })
+            .collect();
+        let total_albums = planned.len();
 
         self.progress
             .println(&format!("Found {} albums to download", total_albums));
 
-        let main_progress = self.progress.create_progress_bar(
-            total_albums as u64,
-            &format!(
-                "Downloading Monster Siren Records library, {} albums",
-                total_albums
-            ),
+        summary.merge(
+            self.download_albums(
+                &planned,
+                &album_index,
+                &format!(
+                    "Downloading Monster Siren Records library, {} albums",
+                    total_albums
+                ),
+                "Download completed!",
+            )
+            .await?,
         );
+        Ok(summary)
+    }
+
+    /// Works out every action downloading the whole library (or only
+    /// `album_ids`, when given) would take under the current options and
+    /// filter, without writing anything. Album and song details are
+    /// requested as for a real download, and every file to download gets a
+    /// `HEAD` request for its size. Files that already exist are left out,
+    /// as a download would skip them.
+    pub async fn plan(&self, album_ids: &[String]) -> Result<Plan> {
+        let root = &self.options.save_path;
+        let mut plan = Plan::default();
+        if !utils::file_exists(root) {
+            plan.push(PlannedAction::CreateDir { path: root.clone() });
+        }
 
-        for (album_index, album_basic) in albums.iter().enumerate() {
-            let album_no = total_albums - album_index;
+        let albums = self.source.get_albums().await?;
+        let (album_index, renames) = AlbumIndex::load(root).await?.plan_update(&albums).await?;
+        for (from, to) in &renames {
+            plan.push(PlannedAction::Rename {
+                from: root.join(from),
+                to: root.join(to),
+            });
+        }
 
-            let album = match self.client.get_album_with_songs(&album_basic.cid).await? {
-                Some(mut album) => {
-                    if album.artistes.is_none() && album_basic.artistes.is_some() {
-                        album.artistes = album_basic.artistes.clone();
-                    }
-                    album
+        let albums = albums
+            .into_iter()
+            .filter(|album| album_ids.is_empty() || album_ids.contains(&album.cid))
+            .collect();
+        let albums: Vec<(Album, Option<Album>)> = stream::iter(self.filter_albums(albums))
+            .map(|album_basic| async move {
+                let album = self.source.get_album_with_songs(&album_basic.cid).await?;
+                Ok::<_, Error>((album_basic, album))
+            })
+            .buffered(self.options.max_concurrent_downloads)
+            .try_collect()
+            .await?;
+        for (album_basic, album) in albums {
+            let Some(mut album) = album else {
+                self.progress
+                    .println(&utils::format_failure_message(&format!(
+                        "⚠️  Cannot get details for album: [{}] {}",
+                        album_basic.cid, album_basic.name
+                    )));
+                continue;
+            };
+            if album.artistes.is_none() {
+                album.artistes = album_basic.artistes.clone();
+            }
+            let Some(only_songs) = self.select_songs(&album, None) else {
+                continue;
+            };
+
+            let album_dir = album_index.get(&album.cid).map_or_else(
+                || AlbumIndex::dir_name(0, &album),
+                |entry| entry.dir_name.clone(),
+            );
+            // Until the planned renames happen, the album's files are still
+            // in its old folder.
+            let current_dir = renames
+                .iter()
+                .find(|(_, to)| *to == album_dir)
+                .map_or(album_dir.as_str(), |(from, _)| from.as_str());
+            self.plan_album(
+                &album,
+                &root.join(&album_dir),
+                &root.join(current_dir),
+                only_songs.as_ref(),
+                &mut plan,
+            )
+            .await?;
+        }
+
+        let downloads = plan.actions.iter_mut().filter_map(|action| match action {
+            PlannedAction::Download { url, size, .. } => Some((url.as_str(), size)),
+            _ => None,
+        });
+        stream::iter(downloads)
+            .for_each_concurrent(
+                self.options.max_concurrent_downloads,
+                |(url, size)| async move {
+                    *size = self.source.file_size(url).await.unwrap_or_else(|e| {
+                        log::warn!("Cannot get the size of {}: {}", url, e);
+                        None
+                    });
+                },
+            )
+            .await;
+
+        Ok(plan)
+    }
+
+    /// Downloads a single album, including its covers, lyrics and metadata.
+    pub async fn download_album(&self, album_id: &str) -> Result<DownloadSummary> {
+        utils::ensure_dir_exists(&self.options.save_path).await?;
+        self.load_manifest().await?;
+
+        let mut summary = DownloadSummary::default();
+        let (album_basic, album_dir) = self.find_album(album_id, &mut summary).await?;
+        let album_summary = self
+            .download_album_entry(&album_basic, &album_dir, None)
+            .await;
+        self.progress.finish_downloads();
+        summary.merge(album_summary?);
+        Ok(summary)
+    }
+
+    /// Downloads a single song into its album directory, along with the
+    /// album covers and info so the track is tagged the same way as a full
+    /// album download.
+    pub async fn download_song(&self, song_id: &str) -> Result<DownloadSummary> {
+        utils::ensure_dir_exists(&self.options.save_path).await?;
+        self.load_manifest().await?;
+
+        let song = self
+            .source
+            .get_song(song_id)
+            .await?
+            .ok_or_else(|| Error::InvalidData(format!("Song not found: {}", song_id)))?;
+        let album_id = song.album_cid.clone().ok_or_else(|| {
+            Error::InvalidData(format!("Song {} does not belong to an album", song_id))
+        })?;
+
+        let mut summary = DownloadSummary::default();
+        let (album_basic, album_dir) = self.find_album(&album_id, &mut summary).await?;
+        let only_songs = HashSet::from([song.cid]);
+        let album_summary = self
+            .download_album_entry(&album_basic, &album_dir, Some(&only_songs))
+            .await;
+        self.progress.finish_downloads();
+        summary.merge(album_summary?);
+        Ok(summary)
+    }
+
+    /// Brings the library up to date with the catalog, fetching details only
+    /// for albums that were never downloaded and for songs that are new or
+    /// whose source or lyric URL changed since they were downloaded.
+    pub async fn sync(&self) -> Result<SyncReport> {
+        utils::ensure_dir_exists(&self.options.save_path).await?;
+        self.load_manifest().await?;
+
+        let albums = self.source.get_albums().await?;
+        let songs = self.source.get_songs().await?;
+        let mut summary = DownloadSummary::default();
+        let album_index = self.update_album_index(&albums, &mut summary).await?;
+
+        let albums = self.filter_albums(albums);
+        let songs = self
+            .get_known_song_details(&albums, songs, &mut summary)
+            .await;
+        let plan = SyncPlan::new(
+            &albums,
+            &songs,
+            &*self.manifest.lock().await,
+            &self.options.filter,
+        );
+        let mut report = plan.report();
+
+        self.progress.println(&format!(
+            "Found {} new albums, {} new songs, {} updated songs, {} songs to retry, {} unchanged songs",
+            report.added_albums.len(),
+            report.added_songs.len(),
+            report.updated_songs.len(),
+            report.retried_songs.len(),
+            report.unchanged_songs
+        ));
+
+        let set_aside = self.set_aside_song_files(&plan.replaced_songs).await?;
+        let download = self
+            .download_albums(
+                &plan.albums,
+                &album_index,
+                &format!("Syncing {} albums", plan.albums.len()),
+                "Sync completed!",
+            )
+            .await;
+        {
+            let mut manifest = self.manifest.lock().await;
+            settle_set_aside_files(&mut manifest, &plan.replaced_songs, set_aside).await?;
+            manifest.save().await?;
+        }
+
+        summary.merge(download?);
+        report.download = summary;
+        Ok(report)
+    }
+
+    /// Replays the items of an earlier run that failed. Album-level failures
+    /// re-run the whole album; track, lyric, song detail and tagging failures
+    /// re-run only the affected songs. Files that already exist are skipped
+    /// as usual.
+    pub async fn retry_failed(&self, failures: &[Failure]) -> Result<DownloadSummary> {
+        utils::ensure_dir_exists(&self.options.save_path).await?;
+        self.load_manifest().await?;
+
+        let mut selection: HashMap<&str, Option<HashSet<String>>> = HashMap::new();
+        for failure in failures {
+            let songs = selection
+                .entry(&failure.album_cid)
+                .or_insert_with(|| Some(HashSet::new()));
+            match (&failure.song_cid, songs) {
+                (Some(song_id), Some(songs)) => {
+                    songs.insert(song_id.clone());
                 }
-                None => {
+                (None, songs) => *songs = None,
+                (Some(_), None) => {}
+            }
+        }
+
+        let albums = self.source.get_albums().await?;
+        let mut summary = DownloadSummary::default();
+        let album_index = self.update_album_index(&albums, &mut summary).await?;
+
+        let planned: Vec<_> = albums
+            .into_iter()
+            .filter_map(|album| {
+                let songs = selection.remove(album.cid.as_str())?;
+                Some(PlannedAlbum { album, songs })
+            })
+            .collect();
+
+        summary.merge(
+            self.download_albums(
+                &planned,
+                &album_index,
+                &format!("Retrying {} albums", planned.len()),
+                "Retry completed!",
+            )
+            .await?,
+        );
+
+        for album_id in selection.into_keys() {
+            self.progress
+                .println(&utils::format_failure_message(&format!(
+                    "⚠️  Album no longer in catalog: {}",
+                    album_id
+                )));
+            summary.albums_failed += 1;
+        }
+
+        Ok(summary)
+    }
+
+    /// Downloads the planned albums, `max_concurrent_albums` at a time. Each
+    /// album saves the manifest once it is done.
+    async fn download_albums(
+        &self,
+        albums: &[PlannedAlbum],
+        album_index: &AlbumIndex,
+        message: &str,
+        finished_message: &'static str,
+    ) -> Result<DownloadSummary> {
+        let mut summary = DownloadSummary::default();
+        let main_progress = self
+            .progress
+            .create_progress_bar(albums.len() as u64, message);
+
+        let mut album_downloads = stream::iter(albums)
+            .map(|planned| async move {
+                let album_dir = album_index.get(&planned.album.cid).map_or_else(
+                    || AlbumIndex::dir_name(0, &planned.album),
+                    |entry| entry.dir_name.clone(),
+                );
+                self.download_album_entry(&planned.album, &album_dir, planned.songs.as_ref())
+                    .await
+            })
+            .buffer_unordered(self.options.max_concurrent_albums);
+
+        while let Some(album_summary) = album_downloads.next().await {
+            summary.merge(album_summary?);
+            main_progress.inc(1);
+        }
+
+        self.progress.finish_downloads();
+
+        main_progress.finish_with_message(finished_message);
+        self.progress.remove_progress_bar(&main_progress);
+        Ok(summary)
+    }
+
+    /// Fetches and downloads one album from the catalog listing. When
+    /// `only_songs` is given, only those tracks are fetched, downloaded and
+    /// tagged.
+    async fn download_album_entry(
+        &self,
+        album_basic: &Album,
+        album_dir: &str,
+        only_songs: Option<&HashSet<String>>,
+    ) -> Result<DownloadSummary> {
+        let mut summary = DownloadSummary::default();
+
+        match self
+            .fetch_album(album_basic, only_songs, &mut summary)
+            .await
+        {
+            Ok(FetchedAlbum::Ready(album, only_songs)) => {
+                self.process_album(&album, album_dir, only_songs.as_ref(), &mut summary)
+                    .await?
+            }
+            Ok(FetchedAlbum::Missing) => summary.albums_failed += 1,
+            Ok(FetchedAlbum::Filtered) => {
+                log::info!(
+                    "Skipping album [{}] {}: excluded by filter",
+                    album_basic.cid,
+                    album_basic.name
+                );
+            }
+            Err(e) => {
+                self.album_failed(
+                    FailureStage::AlbumDetails,
+                    album_basic,
+                    None,
+                    e,
+                    &mut summary,
+                )?;
+                summary.albums_failed += 1;
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Handles an album-level failure according to the error policy: under
+    /// [`ErrorPolicy::FailFast`] the error is returned, otherwise it is
+    /// recorded in `summary`.
+    fn album_failed(
+        &self,
+        stage: FailureStage,
+        album: &Album,
+        url: Option<&str>,
+        error: Error,
+        summary: &mut DownloadSummary,
+    ) -> Result<()> {
+        self.progress
+            .println(&utils::format_failure_message(&format!(
+                "⚠️  Failed to get {} for album [{}] {}: {}",
+                stage.as_str(),
+                album.cid,
+                album.name,
+                error
+            )));
+
+        if self.options.error_policy == ErrorPolicy::FailFast {
+            return Err(error);
+        }
+
+        summary
+            .failures
+            .push(Failure::new(stage, album, None, url, &error));
+        Ok(())
+    }
+
+    /// Drops the albums the filter excludes based on the catalog listing.
+    /// Albums it can't decide on yet are kept until their details are known.
+    fn filter_albums(&self, albums: Vec<Album>) -> Vec<Album> {
+        albums
+            .into_iter()
+            .filter(|album| self.options.filter.album_allowed(album) != Some(false))
+            .collect()
+    }
+
+    /// Replaces the listing entries of the songs already in the library with
+    /// their details, so sync can tell whether their URLs changed. Songs
+    /// whose details can't be fetched are kept as listed and recorded in
+    /// `summary`.
+    async fn get_known_song_details(
+        &self,
+        albums: &[Album],
+        songs: Vec<Song>,
+        summary: &mut DownloadSummary,
+    ) -> Vec<Song> {
+        let albums: HashMap<&str, &Album> = albums
+            .iter()
+            .map(|album| (album.cid.as_str(), album))
+            .collect();
+        let known: HashSet<String> = {
+            let manifest = self.manifest.lock().await;
+            songs
+                .iter()
+                .filter(|song| manifest.song(&song.cid).is_some())
+                .filter(|song| {
+                    song.album_cid
+                        .as_deref()
+                        .and_then(|album_id| albums.get(album_id))
+                        .is_some_and(|album| self.options.filter.song_allowed(album, song))
+                })
+                .map(|song| song.cid.clone())
+                .collect()
+        };
+        if known.is_empty() {
+            return songs;
+        }
+
+        let progress = self.progress.create_progress_bar(
+            known.len() as u64,
+            &format!("Checking {} songs for changes", known.len()),
+        );
+        let (known, progress) = (&known, &progress);
+        let results = stream::iter(songs)
+            .map(|song| async move {
+                if !known.contains(&song.cid) {
+                    return Ok(song);
+                }
+                let result = self.source.get_song(&song.cid).await;
+                progress.inc(1);
+                match result {
+                    Ok(Some(detailed_song)) => Ok(detailed_song),
+                    Ok(None) => Err((song, Error::InvalidData("song details missing".to_string()))),
+                    Err(e) => Err((song, e)),
+                }
+            })
+            .buffered(self.options.max_concurrent_downloads)
+            .collect::<Vec<_>>()
+            .await;
+        self.progress.remove_progress_bar(progress);
+
+        let mut detailed_songs = Vec::new();
+        for result in results {
+            match result {
+                Ok(song) => detailed_songs.push(song),
+                Err((song, e)) => {
                     self.progress
                         .println(&utils::format_failure_message(&format!(
-                            "⚠️  Cannot get details for album: [{}] {}",
-                            album_basic.cid, album_basic.name
+                            "⚠️  Failed to get song details for {}: {}",
+                            song.name, e
                         )));
-                    main_progress.inc(1);
+                    if let Some(album) = song
+                        .album_cid
+                        .as_deref()
+                        .and_then(|album_id| albums.get(album_id))
+                    {
+                        summary.failures.push(Failure::new(
+                            FailureStage::SongDetails,
+                            album,
+                            Some(&song),
+                            None,
+                            &e,
+                        ));
+                    }
+                    detailed_songs.push(song);
+                }
+            }
+        }
+        detailed_songs
+    }
+
+    /// Moves the files previously downloaded for songs out of the way so
+    /// they are fetched again, and forgets them in the manifest until
+    /// [`settle_set_aside_files`] deletes or restores them.
+    async fn set_aside_song_files(&self, songs: &[Song]) -> Result<Vec<SetAsideFile>> {
+        let mut manifest = self.manifest.lock().await;
+        let mut set_aside = Vec::new();
+
+        for song in songs {
+            let paths: Vec<PathBuf> = manifest
+                .song_entries(&song.cid)
+                .map(|entry| manifest.root().join(&entry.path))
+                .collect();
+
+            for path in paths {
+                let Some(entry) = manifest.remove(&path) else {
+                    continue;
+                };
+                if !utils::file_exists(&path) {
                     continue;
                 }
-            };
 
-            let songs = self.get_detailed_songs(&album).await;
-            let album_with_songs = Album {
+                let mut moved_to = path.clone().into_os_string();
+                moved_to.push(".old");
+                let moved_to = PathBuf::from(moved_to);
+                if let Err(e) = tokio::fs::rename(&path, &moved_to).await {
+                    manifest.record(entry);
+                    settle_set_aside_files(&mut manifest, &[], set_aside).await?;
+                    return Err(e.into());
+                }
+                set_aside.push(SetAsideFile { entry, moved_to });
+            }
+        }
+
+        Ok(set_aside)
+    }
+
+    /// Looks up an album in the catalog listing, returning it together with
+    /// the name of its directory in the library.
+    async fn find_album(
+        &self,
+        album_id: &str,
+        summary: &mut DownloadSummary,
+    ) -> Result<(Album, String)> {
+        let albums = self.source.get_albums().await?;
+        let album_index = self.update_album_index(&albums, summary).await?;
+
+        let album = albums
+            .into_iter()
+            .find(|album| album.cid == album_id)
+            .ok_or_else(|| Error::InvalidData(format!("Album not found: {}", album_id)))?;
+        let album_dir = album_index
+            .get(album_id)
+            .map(|entry| entry.dir_name.clone())
+            .unwrap_or_else(|| AlbumIndex::dir_name(0, &album));
+
+        Ok((album, album_dir))
+    }
+
+    /// Loads the persisted album numbering, assigns numbers to new albums
+    /// and moves existing album folders to their current names. A manifest
+    /// that can't be saved afterwards is recorded against the renamed albums
+    /// in `summary`.
+    async fn update_album_index(
+        &self,
+        albums: &[Album],
+        summary: &mut DownloadSummary,
+    ) -> Result<AlbumIndex> {
+        let mut album_index = AlbumIndex::load(&self.options.save_path).await?;
+
+        let renamed = album_index.update(albums).await?;
+        if renamed.is_empty() {
+            return Ok(album_index);
+        }
+
+        let saved = {
+            let mut manifest = self.manifest.lock().await;
+            for (from, to) in &renamed {
+                manifest.rename_dir(from, to);
+                self.progress
+                    .println(&format!("Renamed album folder \"{}\" to \"{}\"", from, to));
+            }
+            manifest.save().await
+        };
+        if let Err(e) = saved {
+            let renamed_albums = albums.iter().filter(|album| {
+                album_index
+                    .get(&album.cid)
+                    .is_some_and(|entry| renamed.iter().any(|(_, to)| *to == entry.dir_name))
+            });
+            for album in renamed_albums {
+                let error = Error::File(format!("Cannot save the manifest: {}", e));
+                self.album_failed(FailureStage::AlbumInfo, album, None, error, summary)?;
+            }
+        }
+
+        Ok(album_index)
+    }
+
+    async fn load_manifest(&self) -> Result<()> {
+        *self.manifest.lock().await = Manifest::load(&self.options.save_path).await?;
+        Ok(())
+    }
+
+    /// Fetches album details and song details. Only the songs selected by
+    /// `only_songs` and the filter get their details fetched; the rest of
+    /// the track list is kept as returned by the album endpoint so track
+    /// numbers stay the same.
+    async fn fetch_album(
+        &self,
+        album_basic: &Album,
+        only_songs: Option<&HashSet<String>>,
+        summary: &mut DownloadSummary,
+    ) -> Result<FetchedAlbum> {
+        let mut album = match self.source.get_album_with_songs(&album_basic.cid).await? {
+            Some(album) => album,
+            None => {
+                self.progress
+                    .println(&utils::format_failure_message(&format!(
+                        "⚠️  Cannot get details for album: [{}] {}",
+                        album_basic.cid, album_basic.name
+                    )));
+                summary.failures.push(Failure::new(
+                    FailureStage::AlbumDetails,
+                    album_basic,
+                    None,
+                    None,
+                    &Error::InvalidData("album details missing".to_string()),
+                ));
+                return Ok(FetchedAlbum::Missing);
+            }
+        };
+
+        if album.artistes.is_none() && album_basic.artistes.is_some() {
+            album.artistes = album_basic.artistes.clone();
+        }
+
+        let Some(only_songs) = self.select_songs(&album, only_songs) else {
+            return Ok(FetchedAlbum::Filtered);
+        };
+        let songs = self
+            .get_detailed_songs(&album, only_songs.as_ref(), summary)
+            .await;
+
+        Ok(FetchedAlbum::Ready(
+            Box::new(Album {
                 songs: Some(songs),
                 ..album
-            };
+            }),
+            only_songs,
+        ))
+    }
 
-            self.progress.set_pinned_message(&format!(
-                "{}: downloading album tracks",
-                utils::format_album_name(&album_with_songs.name)
-            ));
+    /// Narrows `only_songs` down to the songs the filter includes, or returns
+    /// `None` when the filter excludes the whole album or all of its songs.
+    fn select_songs(
+        &self,
+        album: &Album,
+        only_songs: Option<&HashSet<String>>,
+    ) -> Option<Option<HashSet<String>>> {
+        let filter = &self.options.filter;
+        if filter.album_allowed(album) != Some(true) {
+            return None;
+        }
+        if !filter.has_song_rules() {
+            return Some(only_songs.cloned());
+        }
+
+        let selected: HashSet<String> = album
+            .get_songs()
+            .into_iter()
+            .filter(|song| only_songs.is_none_or(|cids| cids.contains(&song.cid)))
+            .filter(|song| filter.song_allowed(album, song))
+            .map(|song| song.cid)
+            .collect();
+        (!selected.is_empty()).then_some(Some(selected))
+    }
 
-            let album_dir_name = format!("{:03} - {}", album_no, album_with_songs.sanitized_name());
-            let album_path = self.save_path.join(album_dir_name);
-            utils::ensure_dir_exists(&album_path).await?;
+    /// Runs the download pipeline for an album whose songs have already been
+    /// fetched. When `only_songs` is set, only those tracks are downloaded
+    /// and tagged.
+    async fn process_album(
+        &self,
+        album: &Album,
+        album_dir: &str,
+        only_songs: Option<&HashSet<String>>,
+        summary: &mut DownloadSummary,
+    ) -> Result<()> {
+        self.progress.set_pinned_message(&format!(
+            "{}: downloading album tracks",
+            utils::format_album_name(&album.name)
+        ));
 
-            self.save_album_info(&album_with_songs, &album_path).await?;
+        let album_path = self.options.save_path.join(album_dir);
+        let album_info = match utils::ensure_dir_exists(&album_path).await {
+            Ok(()) => self.save_album_info(album, &album_path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = album_info {
+            // Without its directory nothing else can be written for the album.
+            self.album_failed(FailureStage::AlbumInfo, album, None, e, summary)?;
+            summary.albums_failed += 1;
+            return Ok(());
+        }
 
-            self.download_album_covers(&album_with_songs, &album_path)
+        let mut album_ok = true;
+        if self.options.download_covers {
+            album_ok = self
+                .download_album_covers(album, &album_path, summary)
                 .await?;
+        }
 
-            self.download_album_songs(&album_with_songs, &album_path)
-                .await?;
+        self.download_album_songs(album, &album_path, only_songs, summary)
+            .await;
 
-            self.apply_metadata_to_songs(&album_with_songs, &album_path)
-                .await?;
+        if self.options.download_mvs {
+            self.download_album_mvs(album, &album_path, only_songs, summary)
+                .await;
+        }
 
+        if self.options.write_metadata {
+            self.apply_metadata_to_songs(album, &album_path, only_songs, summary)
+                .await;
+        }
+
+        let saved = {
+            let mut manifest = self.manifest.lock().await;
+            manifest.record_album(album);
+            manifest.save().await
+        };
+        if let Err(e) = saved {
+            self.album_failed(FailureStage::AlbumInfo, album, None, e, summary)?;
+            album_ok = false;
+        }
+
+        if album_ok {
             self.progress
                 .println(&utils::format_success_message(&format!(
                     "✅  {}",
-                    utils::format_album_name(&album_with_songs.name)
+                    utils::format_album_name(&album.name)
                 )));
-            main_progress.inc(1);
+            summary.albums_completed += 1;
+        } else {
+            summary.albums_failed += 1;
         }
+        Ok(())
+    }
 
-        main_progress.finish_with_message("Download completed!");
-        self.progress.remove_progress_bar(&main_progress);
+    /// Adds the actions for one album to `plan`. `album_path` is where the
+    /// album will be, `current_path` where it is now.
+    async fn plan_album(
+        &self,
+        album: &Album,
+        album_path: &Path,
+        current_path: &Path,
+        only_songs: Option<&HashSet<String>>,
+        plan: &mut Plan,
+    ) -> Result<()> {
+        if !utils::file_exists(current_path) {
+            plan.push(PlannedAction::CreateDir {
+                path: album_path.to_path_buf(),
+            });
+        }
+        if !utils::file_exists(current_path.join("info.txt")) {
+            plan.push(PlannedAction::WriteInfo {
+                path: album_path.join("info.txt"),
+            });
+        }
+
+        let songs: Vec<(usize, Song, Option<Song>)> = stream::iter(
+            album
+                .get_songs()
+                .into_iter()
+                .enumerate()
+                .filter(|(_, song)| song.is_valid())
+                .filter(|(_, song)| only_songs.is_none_or(|cids| cids.contains(&song.cid))),
+        )
+        .map(|(index, song)| async move {
+            let detailed_song = self.source.get_song(&song.cid).await?;
+            Ok::<_, Error>((index, song, detailed_song))
+        })
+        .buffered(self.options.max_concurrent_downloads)
+        .try_collect()
+        .await?;
+
+        let mut download = |url: &str, filename: &str, kind: FileKind| {
+            if !utils::file_exists(current_path.join(filename)) {
+                plan.push(PlannedAction::Download {
+                    url: url.to_string(),
+                    path: album_path.join(filename),
+                    kind,
+                    size: None,
+                });
+            }
+        };
+
+        if self.options.download_covers {
+            if let Some(cover_url) = &album.cover_url {
+                download(
+                    cover_url,
+                    &cover_file_name("Album Cover", cover_url),
+                    FileKind::Cover,
+                );
+            }
+            if let Some(cover_de_url) = &album.cover_de_url {
+                download(
+                    cover_de_url,
+                    &cover_file_name("Cover", cover_de_url),
+                    FileKind::DetailedCover,
+                );
+            }
+        }
+
+        let mut tagged = Vec::new();
+        for (index, song, detailed_song) in songs {
+            let Some(song) = detailed_song else {
+                self.progress
+                    .println(&utils::format_failure_message(&format!(
+                        "⚠️  Song not found: {}",
+                        song.name
+                    )));
+                continue;
+            };
+
+            let track_no = index + 1;
+            if let Some(source_url) = &song.source_url {
+                let filename = track_file_name(track_no, &song, source_url);
+                download(source_url, &filename, FileKind::Audio);
+                tagged.push(album_path.join(filename));
+            }
+            if let Some(lyric_url) = &song.lyric_url
+                && self.options.download_lyrics
+            {
+                download(
+                    lyric_url,
+                    &lyrics_file_name(track_no, &song),
+                    FileKind::Lyrics,
+                );
+            }
+            if let Some(mv_url) = &song.mv_url
+                && self.options.download_mvs
+            {
+                download(
+                    mv_url,
+                    &mv_file_name(track_no, &song, mv_url),
+                    FileKind::MusicVideo,
+                );
+                if let Some(mv_cover_url) = &song.mv_cover_url {
+                    download(
+                        mv_cover_url,
+                        &mv_poster_file_name(track_no, &song, mv_cover_url),
+                        FileKind::MusicVideoPoster,
+                    );
+                }
+            }
+        }
+
+        // Tags are written once all of the album's files are in place.
+        if self.options.write_metadata {
+            for path in tagged {
+                plan.push(PlannedAction::Tag { path });
+            }
+        }
         Ok(())
     }
 
-    async fn get_detailed_songs(&self, album: &Album) -> Vec<Song> {
+    async fn get_detailed_songs(
+        &self,
+        album: &Album,
+        only_songs: Option<&HashSet<String>>,
+        summary: &mut DownloadSummary,
+    ) -> Vec<Song> {
         let songs = album.get_songs();
         let mut detailed_songs = Vec::new();
 
         for song in songs {
-            match self.client.get_song(&song.cid).await {
+            if only_songs.is_some_and(|cids| !cids.contains(&song.cid)) {
+                detailed_songs.push(song);
+                continue;
+            }
+
+            match self.source.get_song(&song.cid).await {
                 Ok(Some(detailed_song)) => detailed_songs.push(detailed_song),
                 Ok(None) => {
                     self.progress
@@ -120,6 +1189,13 @@ impl Downloader {
                             "⚠️  Song not found: {}",
                             song.name
                         )));
+                    summary.failures.push(Failure::new(
+                        FailureStage::SongDetails,
+                        album,
+                        Some(&song),
+                        None,
+                        &Error::InvalidData("song details missing".to_string()),
+                    ));
                     detailed_songs.push(song);
                 }
                 Err(e) => {
@@ -128,6 +1204,13 @@ impl Downloader {
                             "⚠️  Failed to get song details for {}: {}",
                             song.name, e
                         )));
+                    summary.failures.push(Failure::new(
+                        FailureStage::SongDetails,
+                        album,
+                        Some(&song),
+                        None,
+                        &e,
+                    ));
                     detailed_songs.push(song);
                 }
             }
@@ -178,22 +1261,34 @@ impl Downloader {
             if !artists.is_empty() {
                 content.push_str(&format!("  Artists: {}\n", artists.join(", ")));
             }
+            if song.mv_url.is_some() {
+                content.push_str("  Music Video: yes\n");
+            }
         }
 
         tokio::fs::write(info_path, content.trim()).await?;
         Ok(())
     }
 
-    async fn download_album_songs(&self, album: &Album, album_path: &Path) -> Result<()> {
+    /// Downloads every valid track of the album, counting completed and
+    /// failed tracks in `summary`.
+    async fn download_album_songs(
+        &self,
+        album: &Album,
+        album_path: &Path,
+        only_songs: Option<&HashSet<String>>,
+        summary: &mut DownloadSummary,
+    ) {
         let songs = album.get_songs();
         let valid_songs: Vec<_> = songs
             .iter()
             .enumerate()
             .filter(|(_, song)| song.is_valid())
+            .filter(|(_, song)| only_songs.is_none_or(|cids| cids.contains(&song.cid)))
             .collect();
 
         if valid_songs.is_empty() {
-            return Ok(());
+            return;
         }
 
         let song_progress = self.progress.create_progress_bar(
@@ -205,21 +1300,156 @@ impl Downloader {
             ),
         );
 
-        stream::iter(valid_songs)
-            .map(|(index, song)| {
+        let song_progress = &song_progress;
+        let results = stream::iter(valid_songs)
+            .map(|(index, song)| async move {
                 let track_no = index + 1;
-                self.download_song(song, track_no, album_path, &song_progress)
+                let failures = self
+                    .download_track(album, song, track_no, album_path, song_progress)
+                    .await;
+                {
+                    let mut manifest = self.manifest.lock().await;
+                    if failures.is_empty() {
+                        manifest.record_song(&album.cid, song);
+                    } else {
+                        manifest.record_failed_song(&album.cid, song);
+                    }
+                }
+                for failure in &failures {
+                    self.progress
+                        .println(&utils::format_failure_message(&format!(
+                            "⚠️  Failed to download {} of {}: {}",
+                            failure.stage.as_str(),
+                            song.name,
+                            failure.message
+                        )));
+                }
+                failures
             })
-            .buffer_unordered(MAX_CONCURRENT_DOWNLOADS)
+            .buffer_unordered(self.options.max_concurrent_downloads)
             .collect::<Vec<_>>()
             .await;
 
+        for failures in results {
+            if failures.is_empty() {
+                summary.tracks_completed += 1;
+            } else {
+                summary.tracks_failed += 1;
+                summary.failures.extend(failures);
+            }
+        }
+
         song_progress.finish_with_message("Track downloads completed");
-        self.progress.remove_progress_bar(&song_progress);
-        Ok(())
+        self.progress.remove_progress_bar(song_progress);
     }
 
-    async fn apply_metadata_to_songs(&self, album: &Album, album_path: &Path) -> Result<()> {
+    /// Downloads the music video and poster of every selected track that
+    /// has one, at most `max_concurrent_mv_downloads` at a time.
+    async fn download_album_mvs(
+        &self,
+        album: &Album,
+        album_path: &Path,
+        only_songs: Option<&HashSet<String>>,
+        summary: &mut DownloadSummary,
+    ) {
+        let songs = album.get_songs();
+        let mv_songs: Vec<_> = songs
+            .iter()
+            .enumerate()
+            .filter(|(_, song)| song.is_valid() && song.mv_url.is_some())
+            .filter(|(_, song)| only_songs.is_none_or(|cids| cids.contains(&song.cid)))
+            .collect();
+
+        if mv_songs.is_empty() {
+            return;
+        }
+
+        let mv_progress = self.progress.create_progress_bar(
+            mv_songs.len() as u64,
+            &format!(
+                "{}: downloading {} music videos",
+                utils::format_album_name(&album.name),
+                mv_songs.len()
+            ),
+        );
+
+        let mv_progress = &mv_progress;
+        let results = stream::iter(mv_songs)
+            .map(|(index, song)| async move {
+                let failures = self.download_mv(album, song, index + 1, album_path).await;
+                if !failures.is_empty() {
+                    // So that sync retries the song, as it does failed tracks.
+                    self.manifest
+                        .lock()
+                        .await
+                        .record_failed_song(&album.cid, song);
+                }
+                for failure in &failures {
+                    self.progress
+                        .println(&utils::format_failure_message(&format!(
+                            "⚠️  Failed to download music video of {}: {}",
+                            song.name, failure.message
+                        )));
+                }
+                mv_progress.inc(1);
+                failures
+            })
+            .buffer_unordered(self.options.max_concurrent_mv_downloads)
+            .collect::<Vec<_>>()
+            .await;
+
+        summary.failures.extend(results.into_iter().flatten());
+
+        mv_progress.finish_with_message("Music video downloads completed");
+        self.progress.remove_progress_bar(mv_progress);
+    }
+
+    async fn download_mv(
+        &self,
+        album: &Album,
+        song: &Song,
+        track_no: usize,
+        album_path: &Path,
+    ) -> Vec<Failure> {
+        let mut failures = Vec::new();
+        let downloads = [
+            song.mv_url.as_deref().map(|url| {
+                let filename = mv_file_name(track_no, song, url);
+                (url, filename, FileKind::MusicVideo)
+            }),
+            song.mv_cover_url.as_deref().map(|url| {
+                let filename = mv_poster_file_name(track_no, song, url);
+                (url, filename, FileKind::MusicVideoPoster)
+            }),
+        ];
+
+        for (url, filename, kind) in downloads.into_iter().flatten() {
+            let origin = FileOrigin {
+                album_cid: &album.cid,
+                song_cid: Some(&song.cid),
+                kind,
+            };
+            if let Err(e) = self.download_file(url, album_path, &filename, origin).await {
+                failures.push(Failure::new(
+                    FailureStage::MusicVideo,
+                    album,
+                    Some(song),
+                    Some(url),
+                    &e,
+                ));
+            }
+        }
+
+        failures
+    }
+
+    async fn apply_metadata_to_songs(
+        &self,
+        album: &Album,
+        album_path: &Path,
+        only_songs: Option<&HashSet<String>>,
+        summary: &mut DownloadSummary,
+    ) {
         let songs = album.get_songs();
         let valid_songs: Vec<_> = songs
             .iter()
@@ -228,72 +1458,135 @@ impl Downloader {
             .collect();
 
         if valid_songs.is_empty() {
-            return Ok(());
+            return;
         }
 
+        let total_tracks = valid_songs.len();
+        let valid_songs: Vec<_> = valid_songs
+            .into_iter()
+            .filter(|(_, song)| only_songs.is_none_or(|cids| cids.contains(&song.cid)))
+            .collect();
+
         self.progress.set_pinned_message(&format!(
             "{}: applying metadata to tracks",
             utils::format_album_name(&album.name)
         ));
 
-        let total_tracks = valid_songs.len();
         let cover_path = self.find_album_cover(album_path);
 
         for (index, song) in valid_songs {
             let track_no = index + 1;
-            let song_name = song.sanitized_name();
 
-            if song.source_url.is_some() {
-                let ext = utils::get_file_extension(song.source_url.as_ref().unwrap())
-                    .unwrap_or_else(|| ".mp3".to_string());
-                let filename = format!("{:02}.{}{}", track_no, song_name, ext);
+            if let Some(source_url) = &song.source_url {
+                let filename = track_file_name(track_no, song, source_url);
                 let file_path = album_path.join(&filename);
+                if !utils::file_exists(&file_path) {
+                    continue;
+                }
 
-                if utils::file_exists(&file_path) {
-                    if let Err(e) = self
-                        .metadata_writer
-                        .write_metadata(
-                            &file_path,
-                            song,
-                            album,
-                            track_no as u32,
-                            total_tracks as u32,
-                            cover_path.as_deref(),
-                        )
-                        .await
-                    {
-                        eprintln!("Failed to apply metadata to {}: {}", filename, e);
-                    }
+                let lyrics = self
+                    .load_lyrics(&album_path.join(lyrics_file_name(track_no, song)))
+                    .await;
+                if let Err(e) = self
+                    .metadata_writer
+                    .write_metadata(
+                        &file_path,
+                        song,
+                        album,
+                        track_no as u32,
+                        total_tracks as u32,
+                        cover_path.as_deref(),
+                        lyrics.as_ref(),
+                    )
+                    .await
+                {
+                    self.progress
+                        .println(&utils::format_failure_message(&format!(
+                            "⚠️  Failed to apply metadata to {}: {}",
+                            filename, e
+                        )));
+                    summary.failures.push(Failure::new(
+                        FailureStage::Tagging,
+                        album,
+                        Some(song),
+                        None,
+                        &e,
+                    ));
                 }
             }
         }
+    }
 
-        Ok(())
+    /// Reads the lyrics saved next to a track, if there are any. Unreadable
+    /// lyrics are logged and left out of the tags.
+    async fn load_lyrics(&self, lyrics_path: &Path) -> Option<Lyrics> {
+        if !utils::file_exists(lyrics_path) {
+            return None;
+        }
+        match Lyrics::load(lyrics_path).await {
+            Ok(lyrics) => Some(lyrics),
+            Err(e) => {
+                log::warn!("Cannot read lyrics {}: {}", lyrics_path.display(), e);
+                None
+            }
+        }
     }
 
-    async fn download_song(
+    async fn download_track(
         &self,
+        album: &Album,
         song: &Song,
         track_no: usize,
         album_path: &Path,
         progress: &indicatif::ProgressBar,
-    ) -> Result<()> {
-        let song_name = song.sanitized_name();
+    ) -> Vec<Failure> {
+        let mut failures = Vec::new();
+        let origin = FileOrigin {
+            album_cid: &album.cid,
+            song_cid: Some(&song.cid),
+            kind: FileKind::Audio,
+        };
 
         if let Some(source_url) = &song.source_url {
-            let ext = utils::get_file_extension(source_url).unwrap_or_else(|| ".mp3".to_string());
-            let filename = format!("{:02}.{}{}", track_no, song_name, ext);
-            self.download_file(source_url, album_path, &filename)
-                .await?;
+            let filename = track_file_name(track_no, song, source_url);
+            if let Err(e) = self
+                .download_file(source_url, album_path, &filename, origin)
+                .await
+            {
+                failures.push(Failure::new(
+                    FailureStage::Track,
+                    album,
+                    Some(song),
+                    Some(source_url),
+                    &e,
+                ));
+            }
         }
 
-        if let Some(lyric_url) = &song.lyric_url {
-            let filename = format!("{:02}.{}.lrc", track_no, song_name);
-            self.download_file(lyric_url, album_path, &filename).await?;
+        if let Some(lyric_url) = &song.lyric_url
+            && self.options.download_lyrics
+        {
+            let filename = lyrics_file_name(track_no, song);
+            let origin = FileOrigin {
+                kind: FileKind::Lyrics,
+                ..origin
+            };
+            if let Err(e) = self
+                .download_file(lyric_url, album_path, &filename, origin)
+                .await
+            {
+                failures.push(Failure::new(
+                    FailureStage::Lyrics,
+                    album,
+                    Some(song),
+                    Some(lyric_url),
+                    &e,
+                ));
+            }
         }
 
         progress.inc(1);
-        Ok(())
+        failures
     }
 
     fn find_album_cover(&self, album_path: &Path) -> Option<PathBuf> {
@@ -313,15 +1606,33 @@ impl Downloader {
         None
     }
 
-    async fn download_album_covers(&self, album: &Album, album_path: &Path) -> Result<()> {
+    /// Downloads the album covers, returning whether all of them succeeded.
+    async fn download_album_covers(
+        &self,
+        album: &Album,
+        album_path: &Path,
+        summary: &mut DownloadSummary,
+    ) -> Result<bool> {
+        let mut covers_ok = true;
+
         if let Some(cover_url) = &album.cover_url {
             self.progress.set_pinned_message(&format!(
                 "{}: downloading album cover",
                 utils::format_album_name(&album.name)
             ));
-            let ext = utils::get_file_extension(cover_url).unwrap_or_else(|| ".jpg".to_string());
-            let filename = format!("Album Cover{}", ext);
-            self.download_file(cover_url, album_path, &filename).await?;
+            let filename = cover_file_name("Album Cover", cover_url);
+            let origin = FileOrigin {
+                album_cid: &album.cid,
+                song_cid: None,
+                kind: FileKind::Cover,
+            };
+            if let Err(e) = self
+                .download_file(cover_url, album_path, &filename, origin)
+                .await
+            {
+                self.album_failed(FailureStage::Cover, album, Some(cover_url), e, summary)?;
+                covers_ok = false;
+            }
         }
 
         if let Some(cover_de_url) = &album.cover_de_url {
@@ -329,16 +1640,47 @@ impl Downloader {
                 "{}: downloading detailed cover",
                 utils::format_album_name(&album.name)
             ));
-            let ext = utils::get_file_extension(cover_de_url).unwrap_or_else(|| ".jpg".to_string());
-            let filename = format!("Cover{}", ext);
-            self.download_file(cover_de_url, album_path, &filename)
-                .await?;
+            let filename = cover_file_name("Cover", cover_de_url);
+            let origin = FileOrigin {
+                album_cid: &album.cid,
+                song_cid: None,
+                kind: FileKind::DetailedCover,
+            };
+            if let Err(e) = self
+                .download_file(cover_de_url, album_path, &filename, origin)
+                .await
+            {
+                self.album_failed(FailureStage::Cover, album, Some(cover_de_url), e, summary)?;
+                covers_ok = false;
+            }
         }
 
-        Ok(())
+        Ok(covers_ok)
     }
 
-    async fn download_file(&self, url: &str, dir_path: &Path, filename: &str) -> Result<()> {
+    /// Streams `url` into `dir_path/filename` through a temporary file and
+    /// records the result in the manifest. A transfer interrupted mid-stream
+    /// is retried and resumes from the partial file.
+    async fn download_file(
+        &self,
+        url: &str,
+        dir_path: &Path,
+        filename: &str,
+        origin: FileOrigin<'_>,
+    ) -> Result<()> {
+        self.options
+            .retry_policy
+            .run(|| self.download_file_once(url, dir_path, filename, origin))
+            .await
+    }
+
+    async fn download_file_once(
+        &self,
+        url: &str,
+        dir_path: &Path,
+        filename: &str,
+        origin: FileOrigin<'_>,
+    ) -> Result<()> {
         let file_path = dir_path.join(filename);
 
         if utils::file_exists(&file_path) {
@@ -353,23 +1695,121 @@ impl Downloader {
                 .unwrap_or("tmp")
         ));
 
-        if utils::file_exists(&temp_path) {
-            let _ = tokio::fs::remove_file(&temp_path).await;
+        let validator_path = temp_path.with_extension("tmp.validator");
+        let resume_from = self.resumable_offset(&temp_path, &validator_path).await;
+
+        let resume = resume_from.as_ref().map(|(offset, validator)| ResumeFrom {
+            offset: *offset,
+            validator,
+        });
+        let mut response = self.source.fetch_file(url, resume).await?;
+        if response.partial_from.is_some()
+            && response.partial_from != resume.map(|resume| resume.offset)
+        {
+            // A range that doesn't continue the partial file can't be
+            // appended to it, so start over with the whole file.
+            response = self.source.fetch_file(url, None).await?;
+            if response.partial_from.is_some() {
+                return Err(Error::Download(format!(
+                    "Unrequested partial response: {}",
+                    url
+                )));
+            }
         }
+        let etag = response.etag;
+        let last_modified = response.last_modified;
+        let mut hasher = Sha256::new();
+        let mut size = 0;
 
-        let response = self.client.download_file(url).await?;
-        let mut file = tokio::fs::File::create(&temp_path).await?;
+        let mut file = if response.partial_from.is_some() {
+            let mut existing = tokio::fs::File::open(&temp_path).await?;
+            let mut buffer = vec![0; 64 * 1024];
+            loop {
+                let read = existing.read(&mut buffer).await?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+                size += read as u64;
+            }
+            tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(&temp_path)
+                .await?
+        } else {
+            // Either nothing to resume, the server ignored the range, or the
+            // resource changed since the partial download started.
+            match etag
+                .as_ref()
+                .filter(|etag| !etag.starts_with("W/"))
+                .or(last_modified.as_ref())
+            {
+                Some(validator) => tokio::fs::write(&validator_path, validator).await?,
+                None => {
+                    if utils::file_exists(&validator_path) {
+                        tokio::fs::remove_file(&validator_path).await?;
+                    }
+                }
+            }
+            tokio::fs::File::create(&temp_path).await?
+        };
 
-        let mut stream = response.bytes_stream();
+        let mut file_progress =
+            self.progress
+                .start_download(filename, size, response.content_length);
+        let mut stream = response.body;
         while let Some(chunk) = stream.next().await {
             let chunk = chunk?;
+            hasher.update(&chunk);
+            size += chunk.len() as u64;
             file.write_all(&chunk).await?;
+            file_progress.inc(chunk.len() as u64);
         }
 
         file.flush().await?;
         drop(file);
 
-        tokio::fs::rename(temp_path, file_path).await?;
+        tokio::fs::rename(temp_path, &file_path).await?;
+        if utils::file_exists(&validator_path) {
+            let _ = tokio::fs::remove_file(&validator_path).await;
+        }
+        file_progress.finish();
+
+        let mut manifest = self.manifest.lock().await;
+        let path = manifest.relative_path(&file_path);
+        manifest.record(ManifestEntry {
+            album_cid: origin.album_cid.to_string(),
+            song_cid: origin.song_cid.map(str::to_string),
+            kind: origin.kind,
+            source_url: url.to_string(),
+            path,
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+            etag,
+            last_modified,
+            downloaded_at: utils::unix_timestamp(),
+        });
         Ok(())
     }
+
+    /// Returns the size of a partial download and the validator recorded when
+    /// it started, if the partial file can be resumed.
+    async fn resumable_offset(
+        &self,
+        temp_path: &Path,
+        validator_path: &Path,
+    ) -> Option<(u64, String)> {
+        let offset = tokio::fs::metadata(temp_path).await.ok()?.len();
+        if offset == 0 {
+            return None;
+        }
+
+        let validator = tokio::fs::read_to_string(validator_path).await.ok()?;
+        let validator = validator.trim();
+        if validator.is_empty() {
+            return None;
+        }
+
+        Some((offset, validator.to_string()))
+    }
 }
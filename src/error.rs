@@ -1,3 +1,4 @@
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,9 +12,26 @@ pub enum Error {
     #[error("IO operation failed: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("HTTP {status} from {url}")]
+    HttpStatus {
+        url: String,
+        status: u16,
+        retry_after: Option<Duration>,
+    },
+
+    #[error("{source} (gave up after {attempts} attempts)")]
+    RetriesExhausted {
+        attempts: u32,
+        #[source]
+        source: Box<Error>,
+    },
+
     #[error("API error: {message}")]
     Api { message: String },
 
+    #[error("Not available offline: {url}")]
+    Offline { url: String },
+
     #[error("Download failed: {0}")]
     Download(String),
 
@@ -24,4 +42,60 @@ pub enum Error {
     InvalidData(String),
 }
 
+impl Error {
+    /// Whether the failure is likely transient and worth retrying.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Http(e) => {
+                e.is_connect()
+                    || e.is_timeout()
+                    || e.is_body()
+                    || e.is_request()
+                    || (e.is_decode() && !is_json_error(e))
+            }
+            Error::HttpStatus { status, .. } => *status == 429 || (500..600).contains(status),
+            _ => false,
+        }
+    }
+
+    /// Delay requested by the server through a `Retry-After` header.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::HttpStatus { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Short machine-readable name of the error variant. Retried errors
+    /// report the kind of the last failure.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Http(_) => "http",
+            Error::Json(_) => "json",
+            Error::Io(_) => "io",
+            Error::HttpStatus { .. } => "http_status",
+            Error::RetriesExhausted { source, .. } => source.kind(),
+            Error::Api { .. } => "api",
+            Error::Offline { .. } => "offline",
+            Error::Download(_) => "download",
+            Error::File(_) => "file",
+            Error::InvalidData(_) => "invalid_data",
+        }
+    }
+
+    /// Number of attempts made before this error was returned.
+    pub fn attempts(&self) -> u32 {
+        match self {
+            Error::RetriesExhausted { attempts, .. } => *attempts,
+            _ => 1,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// Whether a reqwest decode error comes from malformed JSON rather than a
+/// body cut off mid-transfer, which reqwest reports the same way.
+fn is_json_error(error: &reqwest::Error) -> bool {
+    std::error::Error::source(error).is_some_and(|source| source.is::<serde_json::Error>())
+}
@@ -0,0 +1,214 @@
+use crate::{
+    Error, Result,
+    models::{Album, Song},
+};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// A case-insensitive pattern matched against a whole name: a glob where `*`
+/// matches any run of characters and `?` a single one, or a regular
+/// expression when prefixed with `re:`, which matches anywhere unless
+/// anchored.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let expression = match pattern.strip_prefix("re:") {
+            Some(expression) => expression.to_string(),
+            None => glob_to_regex(pattern),
+        };
+        let regex = RegexBuilder::new(&expression)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| Error::InvalidData(format!("Invalid pattern {}: {}", pattern, e)))?;
+
+        Ok(Self {
+            source: pattern.to_string(),
+            regex,
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = Error;
+
+    fn from_str(pattern: &str) -> Result<Self> {
+        Self::new(pattern)
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Self::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut expression = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => expression.push_str(".*"),
+            '?' => expression.push('.'),
+            _ => expression.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    expression.push('$');
+    expression
+}
+
+/// One side of a [`Filter`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterRules {
+    /// Album cids.
+    pub albums: Vec<String>,
+    pub album_names: Vec<Pattern>,
+    /// Values of the album `belong` field, e.g. `arknights`, compared
+    /// case-insensitively.
+    pub belongs: Vec<String>,
+    /// Song artists, falling back to the album's artists for songs without
+    /// any.
+    pub artists: Vec<Pattern>,
+    pub song_names: Vec<Pattern>,
+}
+
+impl FilterRules {
+    pub fn is_empty(&self) -> bool {
+        self.albums.is_empty()
+            && self.album_names.is_empty()
+            && self.belongs.is_empty()
+            && self.artists.is_empty()
+            && self.song_names.is_empty()
+    }
+
+    fn extend(&mut self, other: FilterRules) {
+        self.albums.extend(other.albums);
+        self.album_names.extend(other.album_names);
+        self.belongs.extend(other.belongs);
+        self.artists.extend(other.artists);
+        self.song_names.extend(other.song_names);
+    }
+
+    fn matches_album(&self, album: &Album) -> bool {
+        self.albums.contains(&album.cid)
+            || self
+                .album_names
+                .iter()
+                .any(|pattern| pattern.is_match(&album.name))
+    }
+
+    fn matches_belong(&self, belong: &str) -> bool {
+        self.belongs
+            .iter()
+            .any(|value| value.eq_ignore_ascii_case(belong))
+    }
+
+    fn matches_artist(&self, artists: &[String]) -> bool {
+        artists
+            .iter()
+            .any(|artist| self.artists.iter().any(|pattern| pattern.is_match(artist)))
+    }
+
+    fn matches_song_name(&self, song: &Song) -> bool {
+        self.song_names
+            .iter()
+            .any(|pattern| pattern.is_match(&song.name))
+    }
+}
+
+/// Selects which albums and songs get downloaded.
+///
+/// An album is included when it matches one of the included `albums` or
+/// `album_names` (if any are given) and one of the included `belongs` (if
+/// any are given). A song of an included album is included when it matches
+/// one of the included `song_names` and `artists` in the same way. Matching
+/// any exclude rule always excludes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filter {
+    pub include: FilterRules,
+    pub exclude: FilterRules,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Adds the rules of `other` to this filter.
+    pub fn merge(&mut self, other: Filter) {
+        self.include.extend(other.include);
+        self.exclude.extend(other.exclude);
+    }
+
+    /// Whether songs are filtered individually, which takes the album's
+    /// track list to decide.
+    pub fn has_song_rules(&self) -> bool {
+        !self.include.song_names.is_empty()
+            || !self.include.artists.is_empty()
+            || !self.exclude.song_names.is_empty()
+            || !self.exclude.artists.is_empty()
+    }
+
+    /// Whether `album` is included, or `None` when that depends on its
+    /// `belong` value and the album doesn't have one. The catalog listing
+    /// lacks `belong`, so such albums are only decided once their details
+    /// are known.
+    pub fn album_allowed(&self, album: &Album) -> Option<bool> {
+        if self.exclude.matches_album(album) {
+            return Some(false);
+        }
+        let selects_albums =
+            !self.include.albums.is_empty() || !self.include.album_names.is_empty();
+        if selects_albums && !self.include.matches_album(album) {
+            return Some(false);
+        }
+
+        if self.include.belongs.is_empty() && self.exclude.belongs.is_empty() {
+            return Some(true);
+        }
+        let belong = album.belong.as_deref()?;
+        if self.exclude.matches_belong(belong) {
+            return Some(false);
+        }
+        Some(self.include.belongs.is_empty() || self.include.matches_belong(belong))
+    }
+
+    pub fn song_allowed(&self, album: &Album, song: &Song) -> bool {
+        let mut artists = song.get_artists();
+        if artists.is_empty() {
+            artists = album.get_artistes();
+        }
+
+        if self.exclude.matches_song_name(song) || self.exclude.matches_artist(&artists) {
+            return false;
+        }
+        (self.include.song_names.is_empty() || self.include.matches_song_name(song))
+            && (self.include.artists.is_empty() || self.include.matches_artist(&artists))
+    }
+}
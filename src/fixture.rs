@@ -0,0 +1,123 @@
+use crate::{
+    Error, Result,
+    models::{Album, Song},
+    source::{CatalogSource, FileResponse, ResumeFrom},
+};
+use bytes::Bytes;
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// In-memory catalog, useful for tests and for replaying a catalog that was
+/// fetched earlier.
+#[derive(Debug, Clone, Default)]
+pub struct FixtureSource {
+    albums: Vec<Album>,
+    songs: HashMap<String, Song>,
+    files: HashMap<String, Bytes>,
+}
+
+impl FixtureSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an album together with its songs, which should carry full
+    /// details. Albums are listed in the order they were added, so add the
+    /// newest release first.
+    pub fn with_album(mut self, album: Album) -> Self {
+        for song in album.get_songs() {
+            self.songs.insert(song.cid.clone(), song);
+        }
+        self.albums.push(album);
+        self
+    }
+
+    /// Serves `content` for requests to `url`.
+    pub fn with_file<U: Into<String>, B: Into<Bytes>>(mut self, url: U, content: B) -> Self {
+        self.files.insert(url.into(), content.into());
+        self
+    }
+
+    pub fn albums(&self) -> &[Album] {
+        &self.albums
+    }
+}
+
+impl CatalogSource for FixtureSource {
+    async fn get_albums(&self) -> Result<Vec<Album>> {
+        Ok(self
+            .albums
+            .iter()
+            .map(|album| Album {
+                songs: None,
+                ..album.clone()
+            })
+            .collect())
+    }
+
+    async fn get_album_with_songs(&self, album_id: &str) -> Result<Option<Album>> {
+        Ok(self
+            .albums
+            .iter()
+            .find(|album| album.cid == album_id)
+            .cloned())
+    }
+
+    async fn get_song(&self, song_id: &str) -> Result<Option<Song>> {
+        Ok(self.songs.get(song_id).cloned())
+    }
+
+    async fn get_songs(&self) -> Result<Vec<Song>> {
+        Ok(self
+            .albums
+            .iter()
+            .flat_map(|album| album.get_songs())
+            .collect())
+    }
+
+    async fn fetch_file(&self, url: &str, resume: Option<ResumeFrom<'_>>) -> Result<FileResponse> {
+        let content = self
+            .files
+            .get(url)
+            .cloned()
+            .ok_or_else(|| Error::HttpStatus {
+                url: url.to_string(),
+                status: 404,
+                retry_after: None,
+            })?;
+
+        let etag = format!("\"{:x}\"", Sha256::digest(&content));
+        Ok(file_response(content, etag, None, resume))
+    }
+
+    async fn file_size(&self, url: &str) -> Result<Option<u64>> {
+        Ok(self.files.get(url).map(|content| content.len() as u64))
+    }
+}
+
+/// Builds a [`FileResponse`] for content held in memory, honouring `resume`
+/// the way an HTTP server honours `Range` with `If-Range`.
+pub(crate) fn file_response(
+    content: Bytes,
+    etag: String,
+    last_modified: Option<String>,
+    resume: Option<ResumeFrom<'_>>,
+) -> FileResponse {
+    let partial_from = resume
+        .filter(|resume| resume.validator == etag && resume.offset < content.len() as u64)
+        .map(|resume| resume.offset);
+
+    let body = match partial_from {
+        Some(offset) => content.slice(offset as usize..),
+        None => content,
+    };
+
+    FileResponse {
+        partial_from,
+        etag: Some(etag),
+        last_modified,
+        content_length: Some(body.len() as u64),
+        body: futures::stream::once(async move { Ok(body) }).boxed(),
+    }
+}
@@ -1,13 +1,45 @@
+pub mod cache;
+pub mod catalog;
 pub mod client;
+pub mod config;
+pub mod diff;
 pub mod download;
 pub mod error;
+pub mod filter;
+pub mod fixture;
+pub mod library;
+pub mod lyrics;
+pub mod manifest;
 pub mod metadata;
+#[cfg(feature = "mock-server")]
+pub mod mock;
 pub mod models;
+pub mod plan;
 pub mod progress;
+pub mod report;
+pub mod retry;
+pub mod snapshot;
+pub mod source;
+pub mod sync;
 pub mod utils;
 
-pub use client::MonsterSirenClient;
-pub use download::Downloader;
+pub use cache::{CachedResponse, ResponseCache};
+pub use catalog::{Catalog, SearchResult};
+pub use client::{MonsterSirenClient, MonsterSirenClientBuilder};
+pub use config::Config;
+pub use diff::CatalogDiff;
+pub use download::{DownloadOptions, Downloader, DownloaderBuilder, ErrorPolicy};
 pub use error::{Error, Result};
-pub use metadata::MetadataWriter;
+pub use filter::{Filter, Pattern};
+pub use fixture::FixtureSource;
+pub use library::AlbumIndex;
+pub use lyrics::{Lyrics, LyricsFormat};
+pub use manifest::{FileKind, Manifest, ManifestEntry};
+pub use metadata::{GenreMap, GenreMapping, MetadataWriter, TagPolicies, TagPolicy};
 pub use models::{Album, Song};
+pub use plan::{Plan, PlannedAction};
+pub use report::{DownloadSummary, Failure, FailureStage};
+pub use retry::RetryPolicy;
+pub use snapshot::{CatalogSnapshot, SnapshotSource};
+pub use source::{CatalogSource, FileResponse, ResumeFrom};
+pub use sync::{SyncPlan, SyncReport};
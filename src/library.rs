@@ -0,0 +1,244 @@
+use crate::{Manifest, Result, models::Album, utils};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Directory inside the library root holding the tool's bookkeeping files.
+pub const STATE_DIR: &str = ".msr-downloader";
+const ALBUM_INDEX_FILE: &str = "albums.json";
+
+/// How an existing album folder is recognised, strongest first.
+#[derive(Debug, Clone, Copy)]
+enum DirMatch {
+    Cid,
+    Name,
+    Number,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlbumEntry {
+    pub number: usize,
+    pub dir_name: String,
+}
+
+/// Persisted album cid → directory number assignment.
+///
+/// Numbers are handed out in release order the first time an album is seen
+/// and never change afterwards, so new releases on the site don't renumber
+/// the folders of albums that are already in the library.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AlbumIndex {
+    #[serde(skip)]
+    root: PathBuf,
+    albums: BTreeMap<String, AlbumEntry>,
+}
+
+impl AlbumIndex {
+    pub async fn load<P: AsRef<Path>>(library_root: P) -> Result<Self> {
+        let root = library_root.as_ref().to_path_buf();
+        let path = root.join(STATE_DIR).join(ALBUM_INDEX_FILE);
+
+        let mut index: AlbumIndex = if utils::file_exists(&path) {
+            let content = tokio::fs::read(&path).await?;
+            serde_json::from_slice(&content)?
+        } else {
+            AlbumIndex::default()
+        };
+        index.root = root;
+        Ok(index)
+    }
+
+    pub async fn save(&self) -> Result<()> {
+        let state_dir = self.root.join(STATE_DIR);
+        utils::ensure_dir_exists(&state_dir).await?;
+        let content = serde_json::to_vec_pretty(self)?;
+        tokio::fs::write(state_dir.join(ALBUM_INDEX_FILE), content).await?;
+        Ok(())
+    }
+
+    pub fn get(&self, album_id: &str) -> Option<&AlbumEntry> {
+        self.albums.get(album_id)
+    }
+
+    /// Directory name for an album, e.g. `012 - Album Name`.
+    pub fn dir_name(number: usize, album: &Album) -> String {
+        format!("{:03} - {}", number, album.sanitized_name())
+    }
+
+    /// Assigns numbers to albums not seen before and renames existing album
+    /// directories in place whenever their expected name changed.
+    ///
+    /// `albums` is the catalog listing, newest release first. Albums that
+    /// already have a `NNN - Name` folder from an earlier run keep that
+    /// number, and a folder is only renamed when its new name is free. When
+    /// a rename fails, the folders already renamed are moved back and the
+    /// index isn't saved, so the disk keeps matching the saved index and
+    /// manifest. Returns the renamed directories as `(from, to)` pairs.
+    pub async fn update(&mut self, albums: &[Album]) -> Result<Vec<(String, String)>> {
+        let renames = self.assign(albums).await?;
+        for (done, (from, to)) in renames.iter().enumerate() {
+            if let Err(e) = tokio::fs::rename(self.root.join(from), self.root.join(to)).await {
+                for (from, to) in renames[..done].iter().rev() {
+                    if let Err(e) =
+                        tokio::fs::rename(self.root.join(to), self.root.join(from)).await
+                    {
+                        log::warn!("Cannot move \"{}\" back to \"{}\": {}", to, from, e);
+                    }
+                }
+                return Err(e.into());
+            }
+        }
+
+        self.save().await?;
+        Ok(renames)
+    }
+
+    /// Works out what [`update`](Self::update) would do without touching
+    /// the disk: returns the updated index along with the directories that
+    /// would be renamed.
+    pub async fn plan_update(&self, albums: &[Album]) -> Result<(Self, Vec<(String, String)>)> {
+        let mut index = self.clone();
+        let renames = index.assign(albums).await?;
+        Ok((index, renames))
+    }
+
+    /// Updates the entries in memory and returns the existing directories
+    /// that need renaming to match them.
+    async fn assign(&mut self, albums: &[Album]) -> Result<Vec<(String, String)>> {
+        self.adopt_dirs(albums).await?;
+
+        // Folders nobody claimed may still belong to albums that left the
+        // listing, so their numbers are not handed out again.
+        let mut used: HashSet<usize> = self.albums.values().map(|entry| entry.number).collect();
+        used.extend(self.scan_album_dirs().await?.iter().map(|dir| dir.0));
+        let mut next_number = used.iter().max().copied().unwrap_or(0) + 1;
+
+        for album in albums.iter().rev() {
+            if self.albums.contains_key(&album.cid) {
+                continue;
+            }
+            while used.contains(&next_number) {
+                next_number += 1;
+            }
+            used.insert(next_number);
+            self.albums.insert(
+                album.cid.clone(),
+                AlbumEntry {
+                    number: next_number,
+                    dir_name: Self::dir_name(next_number, album),
+                },
+            );
+        }
+
+        // Renames are only applied afterwards, so track their effect on the
+        // directories seen so far.
+        let mut renamed: Vec<(String, String)> = Vec::new();
+        let exists = |renamed: &[(String, String)], dir_name: &str| match renamed
+            .iter()
+            .rev()
+            .find(|(from, to)| from == dir_name || to == dir_name)
+        {
+            Some((_, to)) => to == dir_name,
+            None => utils::file_exists(self.root.join(dir_name)),
+        };
+        for album in albums {
+            let Some(entry) = self.albums.get_mut(&album.cid) else {
+                continue;
+            };
+
+            let target = Self::dir_name(entry.number, album);
+            if entry.dir_name == target {
+                continue;
+            }
+
+            // Keep pointing at the current folder when something else is
+            // already in the way of the new name.
+            if !exists(&renamed, &entry.dir_name) {
+                entry.dir_name = target;
+            } else if !exists(&renamed, &target) {
+                renamed.push((entry.dir_name.clone(), target.clone()));
+                entry.dir_name = target;
+            }
+        }
+
+        Ok(renamed)
+    }
+
+    /// Gives albums not in the index the `NNN - Name` folders left by earlier
+    /// runs, before any fresh numbers are handed out.
+    ///
+    /// A folder is matched by the album cid its files were recorded under in
+    /// the manifest, then by name, then by number against the album's
+    /// position in release order, so albums renamed on the site still find
+    /// their folder.
+    async fn adopt_dirs(&mut self, albums: &[Album]) -> Result<()> {
+        let manifest = Manifest::load(&self.root).await?;
+        let dir_cids: HashMap<&str, &str> = manifest
+            .entries()
+            .filter_map(|entry| {
+                let (dir_name, _) = entry.path.split_once('/')?;
+                Some((dir_name, entry.album_cid.as_str()))
+            })
+            .collect();
+
+        let claimed: HashSet<usize> = self.albums.values().map(|entry| entry.number).collect();
+        let mut unclaimed: Vec<(usize, String, String)> = self
+            .scan_album_dirs()
+            .await?
+            .into_iter()
+            .filter(|(number, _, _)| !claimed.contains(number))
+            .collect();
+
+        for rule in [DirMatch::Cid, DirMatch::Name, DirMatch::Number] {
+            for (index, album) in albums.iter().rev().enumerate() {
+                if self.albums.contains_key(&album.cid) {
+                    continue;
+                }
+
+                let sanitized_name = album.sanitized_name();
+                let found = unclaimed.iter().position(|(number, name, dir_name)| {
+                    let recorded = dir_cids.get(dir_name.as_str()).copied();
+                    match rule {
+                        DirMatch::Cid => recorded == Some(album.cid.as_str()),
+                        DirMatch::Name => recorded.is_none() && *name == sanitized_name,
+                        DirMatch::Number => recorded.is_none() && *number == index + 1,
+                    }
+                });
+                if let Some(found) = found {
+                    let (number, _, dir_name) = unclaimed.remove(found);
+                    self.albums
+                        .insert(album.cid.clone(), AlbumEntry { number, dir_name });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists `NNN - Name` directories in the library root as
+    /// `(number, name, dir_name)`, sorted by directory name.
+    async fn scan_album_dirs(&self) -> Result<Vec<(usize, String, String)>> {
+        let mut dirs = Vec::new();
+        if !utils::file_exists(&self.root) {
+            return Ok(dirs);
+        }
+
+        let mut entries = tokio::fs::read_dir(&self.root).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if !entry.file_type().await?.is_dir() {
+                continue;
+            }
+            let Some(dir_name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if let Some((number, name)) = dir_name.split_once(" - ")
+                && let Ok(number) = number.parse::<usize>()
+            {
+                dirs.push((number, name.to_string(), dir_name.clone()));
+            }
+        }
+
+        dirs.sort_by(|a, b| a.2.cmp(&b.2));
+        Ok(dirs)
+    }
+}
@@ -0,0 +1,576 @@
+use crate::{Error, Result, library::STATE_DIR, utils};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// How long the last cue is shown when the lyrics don't say when the track
+/// ends.
+const LAST_CUE_MS: u32 = 5000;
+
+/// A word of enhanced LRC (`<mm:ss.xx>word`) with the time it starts at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LyricWord {
+    pub time_ms: u32,
+    pub text: String,
+}
+
+/// A line of lyrics with the time it starts at, when known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LyricLine {
+    /// Milliseconds from the start of the track.
+    pub time_ms: Option<u32>,
+    pub text: String,
+    /// Word-level timing, empty unless the line uses enhanced LRC.
+    pub words: Vec<LyricWord>,
+}
+
+/// A malformed line found while parsing, which was left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LyricIssue {
+    /// 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for LyricIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Formats lyrics can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LyricsFormat {
+    Text,
+    Srt,
+    WebVtt,
+    Ttml,
+}
+
+impl LyricsFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LyricsFormat::Text => "txt",
+            LyricsFormat::Srt => "srt",
+            LyricsFormat::WebVtt => "vtt",
+            LyricsFormat::Ttml => "ttml",
+        }
+    }
+}
+
+/// Lyrics read from an LRC file.
+///
+/// Lines carrying several timestamps (`[00:12.00][01:24.00]Chorus`) are
+/// repeated for each of them, an `[offset:…]` tag is applied to every
+/// timestamp, and other ID tags such as `[ar:…]` are kept in `tags`.
+/// Synchronised lyrics are sorted by time. Malformed lines are left out and
+/// listed in `issues`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lyrics {
+    /// ID tags by lowercase key, e.g. `ar` → artist.
+    pub tags: BTreeMap<String, String>,
+    pub lines: Vec<LyricLine>,
+    pub issues: Vec<LyricIssue>,
+}
+
+/// A line shown on screen from `start_ms` to `end_ms`. Lines sharing a
+/// timestamp, such as a lyric and its translation, form a single cue.
+struct Cue<'a> {
+    start_ms: u32,
+    end_ms: u32,
+    lines: Vec<&'a LyricLine>,
+}
+
+impl Lyrics {
+    pub fn parse(content: &str) -> Self {
+        let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+        let mut lyrics = Lyrics::default();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Err(message) = lyrics.parse_line(line) {
+                lyrics.issues.push(LyricIssue {
+                    line: index + 1,
+                    message,
+                });
+            }
+        }
+
+        let offset_ms: i64 = lyrics
+            .tags
+            .get("offset")
+            .and_then(|offset| offset.parse().ok())
+            .unwrap_or_default();
+        // A positive offset shows the lyrics earlier.
+        let shift = |time_ms: &mut u32| {
+            *time_ms = (i64::from(*time_ms) - offset_ms).clamp(0, i64::from(u32::MAX)) as u32;
+        };
+        for line in &mut lyrics.lines {
+            if let Some(time_ms) = &mut line.time_ms {
+                shift(time_ms);
+            }
+            for word in &mut line.words {
+                shift(&mut word.time_ms);
+            }
+        }
+
+        if lyrics.is_synced() {
+            lyrics.lines.sort_by_key(|line| line.time_ms);
+        }
+        lyrics
+    }
+
+    /// Parses an LRC file, decoding it as UTF-8 (with or without a byte
+    /// order mark), UTF-16 with a byte order mark, or GB18030, which older
+    /// Chinese lyrics often use.
+    pub fn from_bytes(content: &[u8]) -> Self {
+        Self::parse(&decode(content))
+    }
+
+    pub async fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = tokio::fs::read(path).await?;
+        Ok(Self::from_bytes(&content))
+    }
+
+    fn parse_line(&mut self, line: &str) -> std::result::Result<(), String> {
+        let (times, text) = split_timestamps(line)?;
+        if times.is_empty() {
+            if let Some((key, value)) = parse_id_tag(line) {
+                let key = key.to_ascii_lowercase();
+                let value = value.trim();
+                if key == "offset" && value.parse::<i64>().is_err() {
+                    return Err(format!("invalid offset: {}", value));
+                }
+                self.tags.insert(key, value.to_string());
+            } else if line.starts_with('[') {
+                return Err(format!("unrecognised tag: {}", line));
+            } else {
+                self.lines.push(LyricLine {
+                    time_ms: None,
+                    text: line.to_string(),
+                    words: Vec::new(),
+                });
+            }
+            return Ok(());
+        }
+
+        let (text, words) = parse_words(text)?;
+        for time_ms in times {
+            self.lines.push(LyricLine {
+                time_ms: Some(time_ms),
+                text: text.clone(),
+                words: words.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|line| line.text.is_empty())
+    }
+
+    /// Whether every line has a timestamp.
+    pub fn is_synced(&self) -> bool {
+        !self.lines.is_empty() && self.lines.iter().all(|line| line.time_ms.is_some())
+    }
+
+    /// The lyrics without timestamps, one line per line. Empty lines that
+    /// only mark a pause in synchronised lyrics are left out.
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .filter(|line| !line.text.is_empty())
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// `(milliseconds, text)` pairs for synchronised lyrics, or `None` when
+    /// some line has no timestamp.
+    pub fn synced_lines(&self) -> Option<Vec<(u32, String)>> {
+        if !self.is_synced() {
+            return None;
+        }
+        Some(
+            self.lines
+                .iter()
+                .filter_map(|line| Some((line.time_ms?, line.text.clone())))
+                .collect(),
+        )
+    }
+
+    /// Converts the lyrics to `format`. Every format but plain text needs
+    /// synchronised lyrics.
+    pub fn export(&self, format: LyricsFormat) -> Result<String> {
+        Ok(match format {
+            LyricsFormat::Text => self.text() + "\n",
+            LyricsFormat::Srt => to_srt(&self.cues()?),
+            LyricsFormat::WebVtt => to_webvtt(&self.cues()?),
+            LyricsFormat::Ttml => to_ttml(&self.cues()?, self.tags.get("la")),
+        })
+    }
+
+    /// Groups synchronised lines into cues, each ending when the next one
+    /// starts. The last one ends at the `[length:…]` tag when given.
+    fn cues(&self) -> Result<Vec<Cue<'_>>> {
+        if !self.is_synced() {
+            return Err(Error::InvalidData("lyrics have no timestamps".to_string()));
+        }
+
+        let mut cues: Vec<Cue<'_>> = Vec::new();
+        for line in &self.lines {
+            let Some(time_ms) = line.time_ms else {
+                continue;
+            };
+            match cues.last_mut() {
+                Some(cue) if cue.start_ms == time_ms => cue.lines.push(line),
+                _ => cues.push(Cue {
+                    start_ms: time_ms,
+                    end_ms: time_ms,
+                    lines: vec![line],
+                }),
+            }
+        }
+
+        let length_ms = self
+            .tags
+            .get("length")
+            .and_then(|length| parse_timestamp(length.trim()));
+        let starts: Vec<u32> = cues.iter().map(|cue| cue.start_ms).collect();
+        for (index, cue) in cues.iter_mut().enumerate() {
+            cue.end_ms = match starts.get(index + 1) {
+                Some(next) => *next,
+                None => length_ms
+                    .filter(|length| *length > cue.start_ms)
+                    .unwrap_or(cue.start_ms.saturating_add(LAST_CUE_MS)),
+            };
+        }
+
+        // Empty lines only mark where the previous cue ends.
+        cues.retain(|cue| cue.lines.iter().any(|line| !line.text.is_empty()));
+        Ok(cues)
+    }
+}
+
+/// What converting a directory of `.lrc` files did.
+#[derive(Debug, Default)]
+pub struct ConversionSummary {
+    pub converted: usize,
+    /// Files left alone because the output already existed.
+    pub skipped: usize,
+    pub failed: Vec<(PathBuf, String)>,
+    pub issues: Vec<(PathBuf, LyricIssue)>,
+}
+
+/// Writes every `.lrc` file under `root` in each of `formats`, next to the
+/// original with the format's extension. Existing files are only replaced
+/// when `overwrite` is set.
+pub async fn convert_dir(
+    root: &Path,
+    formats: &[LyricsFormat],
+    overwrite: bool,
+) -> Result<ConversionSummary> {
+    let mut summary = ConversionSummary::default();
+
+    for lrc_path in find_lrc_files(root).await? {
+        let lyrics = match Lyrics::load(&lrc_path).await {
+            Ok(lyrics) => lyrics,
+            Err(e) => {
+                summary.failed.push((lrc_path, e.to_string()));
+                continue;
+            }
+        };
+        summary.issues.extend(
+            lyrics
+                .issues
+                .iter()
+                .map(|issue| (lrc_path.clone(), issue.clone())),
+        );
+
+        for format in formats {
+            let output = lrc_path.with_extension(format.extension());
+            if !overwrite && utils::file_exists(&output) {
+                summary.skipped += 1;
+                continue;
+            }
+            let written = match lyrics.export(*format) {
+                Ok(content) => tokio::fs::write(&output, content)
+                    .await
+                    .map_err(Error::from),
+                Err(e) => Err(e),
+            };
+            match written {
+                Ok(()) => summary.converted += 1,
+                Err(e) => summary.failed.push((output, e.to_string())),
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Lists the `.lrc` files under `root`, skipping the tool's state
+/// directory, sorted by path.
+async fn find_lrc_files(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                if entry.file_name() != STATE_DIR {
+                    dirs.push(path);
+                }
+            } else if path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("lrc"))
+            {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+fn decode(content: &[u8]) -> String {
+    if let Some((encoding, bom_length)) = encoding_rs::Encoding::for_bom(content) {
+        let (text, _) = encoding.decode_without_bom_handling(&content[bom_length..]);
+        return text.into_owned();
+    }
+    match std::str::from_utf8(content) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::GB18030.decode(content).0.into_owned(),
+    }
+}
+
+/// Splits the leading `[mm:ss.xx]` timestamps off a line, returning them in
+/// milliseconds along with the rest of the line.
+fn split_timestamps(mut line: &str) -> std::result::Result<(Vec<u32>, &str), String> {
+    let mut times = Vec::new();
+    while let Some(rest) = line.strip_prefix('[') {
+        let Some((tag, rest)) = rest.split_once(']') else {
+            return Err(format!("unterminated tag: {}", line));
+        };
+        if !looks_like_timestamp(tag) {
+            break;
+        }
+        let time_ms =
+            parse_timestamp(tag).ok_or_else(|| format!("invalid timestamp: [{}]", tag))?;
+        times.push(time_ms);
+        line = rest;
+    }
+    Ok((times, line))
+}
+
+/// Splits enhanced LRC word timing (`<mm:ss.xx>`) out of a line's text,
+/// returning the plain text and the timed words.
+fn parse_words(text: &str) -> std::result::Result<(String, Vec<LyricWord>), String> {
+    let text = text.trim();
+    if !text.contains('<') {
+        return Ok((text.to_string(), Vec::new()));
+    }
+
+    let mut plain = String::new();
+    let mut words = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        if !looks_like_timestamp(tag) {
+            append_word(&mut words, &mut plain, &rest[..start + end + 1]);
+            rest = &rest[start + end + 1..];
+            continue;
+        }
+        let time_ms =
+            parse_timestamp(tag).ok_or_else(|| format!("invalid word timestamp: <{}>", tag))?;
+
+        append_word(&mut words, &mut plain, &rest[..start]);
+        words.push(LyricWord {
+            time_ms,
+            text: String::new(),
+        });
+        rest = &rest[start + end + 1..];
+    }
+    append_word(&mut words, &mut plain, rest);
+
+    // A trailing timestamp only marks where the last word ends.
+    words.retain(|word| !word.text.is_empty());
+    Ok((plain.trim().to_string(), words))
+}
+
+/// Adds `text` to the plain line and to the word started last.
+fn append_word(words: &mut [LyricWord], plain: &mut String, text: &str) {
+    plain.push_str(text);
+    if let Some(word) = words.last_mut() {
+        word.text.push_str(text);
+    }
+}
+
+fn looks_like_timestamp(tag: &str) -> bool {
+    tag.starts_with(|c: char| c.is_ascii_digit())
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == ':' || c == '.')
+}
+
+/// Parses `mm:ss`, `mm:ss.x`, `mm:ss.xx`, `mm:ss.xxx` or `mm:ss:xx`.
+fn parse_timestamp(tag: &str) -> Option<u32> {
+    let (minutes, rest) = tag.split_once(':')?;
+    let (seconds, fraction) = match rest.split_once(['.', ':']) {
+        Some((seconds, fraction)) => (seconds, fraction),
+        None => (rest, ""),
+    };
+
+    let minutes: u32 = minutes.trim().parse().ok()?;
+    let seconds: u32 = seconds.trim().parse().ok()?;
+    if seconds >= 60 || fraction.len() > 3 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let fraction_ms = match fraction.len() {
+        0 => 0,
+        len => fraction.parse::<u32>().ok()? * 10u32.pow(3 - len as u32),
+    };
+
+    minutes
+        .checked_mul(60_000)?
+        .checked_add(seconds * 1000 + fraction_ms)
+}
+
+/// Splits an ID tag such as `[ar:Artist]` into its key and value.
+fn parse_id_tag(line: &str) -> Option<(&str, &str)> {
+    let tag = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (key, value) = tag.split_once(':')?;
+    let key = key.trim();
+    (!key.is_empty() && key.chars().all(|c| c.is_ascii_alphabetic())).then_some((key, value))
+}
+
+/// Formats milliseconds as `HH:MM:SS` followed by `separator` and the
+/// milliseconds.
+fn clock_time(time_ms: u32, separator: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        time_ms / 3_600_000,
+        time_ms / 60_000 % 60,
+        time_ms / 1000 % 60,
+        separator,
+        time_ms % 1000
+    )
+}
+
+fn cue_text<'a>(cue: &'a Cue<'_>) -> Vec<&'a str> {
+    cue.lines
+        .iter()
+        .filter(|line| !line.text.is_empty())
+        .map(|line| line.text.as_str())
+        .collect()
+}
+
+fn to_srt(cues: &[Cue<'_>]) -> String {
+    let mut srt = String::new();
+    for (index, cue) in cues.iter().enumerate() {
+        srt.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            index + 1,
+            clock_time(cue.start_ms, ','),
+            clock_time(cue.end_ms, ','),
+            cue_text(cue).join("\n")
+        ));
+    }
+    srt
+}
+
+/// WebVTT, with word timing as inline timestamps for karaoke-style display.
+fn to_webvtt(cues: &[Cue<'_>]) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    for cue in cues {
+        vtt.push_str(&format!(
+            "{} --> {}\n",
+            clock_time(cue.start_ms, '.'),
+            clock_time(cue.end_ms, '.')
+        ));
+        for line in cue.lines.iter().filter(|line| !line.text.is_empty()) {
+            if line.words.is_empty() {
+                vtt.push_str(&escape_vtt(&line.text));
+            } else {
+                for (index, word) in line.words.iter().enumerate() {
+                    if index > 0 || word.time_ms > cue.start_ms {
+                        vtt.push_str(&format!("<{}>", clock_time(word.time_ms, '.')));
+                    }
+                    vtt.push_str(&escape_vtt(&word.text));
+                }
+            }
+            vtt.push('\n');
+        }
+        vtt.push('\n');
+    }
+    vtt
+}
+
+/// TTML, with word timing as timed `<span>`s.
+fn to_ttml(cues: &[Cue<'_>], language: Option<&String>) -> String {
+    let mut ttml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    ttml.push_str("<tt xmlns=\"http://www.w3.org/ns/ttml\"");
+    if let Some(language) = language {
+        ttml.push_str(&format!(" xml:lang=\"{}\"", escape_xml(language)));
+    }
+    ttml.push_str(">\n  <body>\n    <div>\n");
+
+    for cue in cues {
+        let begin = clock_time(cue.start_ms, '.');
+        let end = clock_time(cue.end_ms, '.');
+        let lines: Vec<String> = cue
+            .lines
+            .iter()
+            .filter(|line| !line.text.is_empty())
+            .map(|line| {
+                if line.words.is_empty() {
+                    return escape_xml(&line.text);
+                }
+                let mut spans = String::new();
+                for (index, word) in line.words.iter().enumerate() {
+                    let word_end = line
+                        .words
+                        .get(index + 1)
+                        .map_or(cue.end_ms, |next| next.time_ms);
+                    spans.push_str(&format!(
+                        "<span begin=\"{}\" end=\"{}\">{}</span>",
+                        clock_time(word.time_ms, '.'),
+                        clock_time(word_end, '.'),
+                        escape_xml(&word.text)
+                    ));
+                }
+                spans
+            })
+            .collect();
+        ttml.push_str(&format!(
+            "      <p begin=\"{}\" end=\"{}\">{}</p>\n",
+            begin,
+            end,
+            lines.join("<br/>")
+        ));
+    }
+
+    ttml.push_str("    </div>\n  </body>\n</tt>\n");
+    ttml
+}
+
+fn escape_vtt(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_xml(text: &str) -> String {
+    escape_vtt(text)
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
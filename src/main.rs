@@ -1,19 +1,563 @@
-use msr_downloader::{Downloader, MonsterSirenClient, Result};
+mod cli;
+
+use clap::Parser;
+use cli::{Cli, Command, FilterArgs, SearchKind};
+use msr_downloader::catalog::ResultKind;
+use msr_downloader::config::CONFIG_FILE;
+use msr_downloader::lyrics;
+use msr_downloader::{
+    Album, Catalog, CatalogDiff, CatalogSnapshot, Config, DownloadSummary, Downloader, Error,
+    ErrorPolicy, LyricsFormat, MonsterSirenClient, Plan, PlannedAction, Result, SnapshotSource,
+    Song, SyncReport, utils,
+};
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Exit code used when the run finished but some albums or tracks failed.
+const EXIT_PARTIAL_FAILURE: u8 = 2;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    env_logger::Builder::new()
+        .filter_level(cli.global.log_level())
+        .parse_default_env()
+        .init();
 
+    match run(cli).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!(
+                "{}",
+                utils::format_failure_message(&format!("Error: {}", e))
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<ExitCode> {
     let version = option_env!("CARGO_PKG_VERSION");
+    let client = build_client(&cli, version)?;
+    let config = load_config(&cli).await?;
+
+    match &cli.command {
+        Command::Download {
+            albums,
+            filter,
+            dry_run: true,
+            json,
+            ..
+        } => {
+            let downloader = build_downloader(client, &cli, &config, Some(filter));
+            let plan = downloader.plan(albums).await?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&plan)?);
+            } else {
+                print_plan(&plan);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Download {
+            albums,
+            songs,
+            filter,
+            ..
+        } if !albums.is_empty() || !songs.is_empty() => {
+            let downloader = build_downloader(client, &cli, &config, Some(filter));
+
+            let mut summary = DownloadSummary::default();
+            for album_id in albums {
+                summary.merge(downloader.download_album(album_id).await?);
+            }
+            for song_id in songs {
+                summary.merge(downloader.download_song(song_id).await?);
+            }
+            finish_run(&summary, &cli).await
+        }
+        Command::Sync { filter } => {
+            let downloader = build_downloader(client, &cli, &config, Some(filter));
+
+            let report = downloader.sync().await?;
+            print_sync_report(&report);
+            finish_run(&report.download, &cli).await
+        }
+        Command::Download { filter, .. } => {
+            println!("Monster Siren Downloader v{}", version.unwrap_or("dev"));
+            println!("Starting Monster Siren Records music library download...");
+
+            let downloader = build_downloader(client, &cli, &config, Some(filter));
+
+            let summary = downloader.download_all_tracks().await?;
+            finish_run(&summary, &cli).await
+        }
+        Command::RetryFailed { report } => {
+            let previous = DownloadSummary::load_json(report).await?;
+            if previous.failures.is_empty() {
+                println!("No failures recorded in {}", report.display());
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            println!("Retrying {} failed items...", previous.failures.len());
+            let downloader = build_downloader(client, &cli, &config, None);
+
+            let summary = downloader.retry_failed(&previous.failures).await?;
+            finish_run(&summary, &cli).await
+        }
+        Command::ListAlbums => {
+            for album in client.get_albums().await? {
+                println!(
+                    "{}\t{}\t{}",
+                    album.cid,
+                    album.name,
+                    album.get_artistes().join(", ")
+                );
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::ListSongs => {
+            let (songs, _) = client.get_songs().await?;
+            for song in songs {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    song.cid,
+                    song.name,
+                    song.album_cid.as_deref().unwrap_or(""),
+                    song.get_artists().join(", ")
+                );
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Info { cid } => print_info(&client, cid).await,
+        Command::Search {
+            query,
+            snapshot,
+            kind,
+            limit,
+            json,
+        } => {
+            let catalog = match snapshot {
+                Some(path) => Catalog::from(CatalogSnapshot::load(path).await?),
+                None => Catalog::fetch(&client).await?,
+            };
+            let results: Vec<_> = catalog
+                .search(&query.join(" "))
+                .into_iter()
+                .filter(|result| match kind {
+                    Some(SearchKind::Album) => result.kind == ResultKind::Album,
+                    Some(SearchKind::Song) => result.kind == ResultKind::Song,
+                    None => true,
+                })
+                .take(*limit)
+                .collect();
+
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&results)?);
+            } else {
+                for result in &results {
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        result.kind.as_str(),
+                        result.cid,
+                        result.name,
+                        result.artists.join(", ")
+                    );
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Snapshot {
+            file,
+            max_requests,
+            with_files,
+        } => {
+            let snapshot = if *with_files {
+                SnapshotSource::create(&client, file, *max_requests).await?
+            } else {
+                let snapshot = CatalogSnapshot::fetch(&client, *max_requests).await?;
+                snapshot.save(file).await?;
+                snapshot
+            };
+            println!(
+                "Saved {} albums and {} songs to {}",
+                snapshot.albums.len(),
+                snapshot
+                    .albums
+                    .iter()
+                    .map(|album| album.get_songs().len())
+                    .sum::<usize>(),
+                file.display()
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::ConvertLyrics {
+            formats,
+            overwrite,
+            dir,
+        } => {
+            let formats: Vec<LyricsFormat> = formats.iter().map(|&format| format.into()).collect();
+            let dir = dir.as_ref().unwrap_or(&cli.global.output);
+            let summary = lyrics::convert_dir(dir, &formats, *overwrite).await?;
+
+            for (path, issue) in &summary.issues {
+                log::warn!("{}: {}", path.display(), issue);
+            }
+            for (path, error) in &summary.failed {
+                eprintln!(
+                    "{}",
+                    utils::format_failure_message(&format!(
+                        "⚠️  Failed to write {}: {}",
+                        path.display(),
+                        error
+                    ))
+                );
+            }
+            println!(
+                "Converted {} files, skipped {} existing, {} failed, {} malformed lines",
+                summary.converted,
+                summary.skipped,
+                summary.failed.len(),
+                summary.issues.len()
+            );
+
+            if summary.failed.is_empty() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::from(EXIT_PARTIAL_FAILURE))
+            }
+        }
+        Command::Diff { old, new, json } => {
+            let old = CatalogSnapshot::load(old).await?;
+            let new = CatalogSnapshot::load(new).await?;
+            let diff = CatalogDiff::new(&old, &new);
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            } else {
+                print_diff(&diff);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+fn build_client(cli: &Cli, version: Option<&str>) -> Result<MonsterSirenClient> {
+    let mut builder = MonsterSirenClient::builder()
+        .timeout(Duration::from_secs(cli.global.timeout))
+        .retry_policy(cli.global.retry_policy());
+
+    if let Some(base_url) = &cli.global.base_url {
+        builder = builder.base_url(base_url);
+    }
+
+    // A dry run must not write to disk, so it only reads the cache when
+    // offline, where the cache is all there is.
+    let dry_run = matches!(cli.command, Command::Download { dry_run: true, .. });
+    if let Some(cache) = cli.global.response_cache()
+        && (!dry_run || cache.is_offline())
+    {
+        builder = builder.cache(cache);
+    }
+
+    match (&cli.global.user_agent, version) {
+        (Some(user_agent), _) => builder = builder.user_agent(user_agent),
+        (None, Some(v)) => builder = builder.user_agent(format!("msr-downloader/{}", v)),
+        (None, None) => {}
+    }
+
+    for header in &cli.global.headers {
+        let (name, value) = header.split_once(':').ok_or_else(|| {
+            Error::InvalidData(format!("Header must be \"Name: value\": {}", header))
+        })?;
+        builder = builder.header(name.trim(), value.trim());
+    }
+
+    builder.build()
+}
+
+/// Reads `--config`, or `msr-downloader.toml` in the working directory when
+/// it exists.
+async fn load_config(cli: &Cli) -> Result<Config> {
+    match &cli.global.config {
+        Some(path) => Config::load(path).await,
+        None if utils::file_exists(CONFIG_FILE) => Config::load(CONFIG_FILE).await,
+        None => Ok(Config::default()),
+    }
+}
+
+fn build_downloader(
+    client: MonsterSirenClient,
+    cli: &Cli,
+    config: &Config,
+    filter_args: Option<&FilterArgs>,
+) -> Downloader {
+    let mut filter = config.filter.clone();
+    if let Some(filter_args) = filter_args {
+        filter.merge(filter_args.filter());
+    }
+
+    Downloader::builder(client)
+        .save_path(&cli.global.output)
+        .max_concurrent_downloads(cli.global.concurrency)
+        .max_concurrent_albums(cli.global.album_concurrency)
+        .download_lyrics(!cli.global.no_lyrics)
+        .download_covers(!cli.global.no_covers)
+        .download_mvs(cli.global.mvs)
+        .max_concurrent_mv_downloads(cli.global.mv_concurrency)
+        .write_metadata(!cli.global.no_tags)
+        .tag_policies(config.tags.clone())
+        .genres(config.genres.clone())
+        .force_retag(cli.global.force_retag)
+        .retry_policy(cli.global.retry_policy())
+        .error_policy(if cli.global.fail_fast {
+            ErrorPolicy::FailFast
+        } else {
+            ErrorPolicy::Continue
+        })
+        .filter(filter)
+        .build()
+}
+
+async fn print_info(client: &MonsterSirenClient, cid: &str) -> Result<ExitCode> {
+    // The API may answer a song cid with an error rather than no album, so
+    // only network and HTTP failures end the lookup here.
+    let album_error = match client.get_album_with_songs(cid).await {
+        Ok(Some(album)) => {
+            print_album_info(&album);
+            return Ok(ExitCode::SUCCESS);
+        }
+        Ok(None) => None,
+        Err(e @ Error::Api { .. }) => Some(e),
+        Err(e) => return Err(e),
+    };
+
+    match client.get_song(cid).await? {
+        Some(song) => {
+            print_song_info(&song);
+            Ok(ExitCode::SUCCESS)
+        }
+        None => {
+            let message = match album_error {
+                Some(e) => format!("No album or song found for {} (album lookup: {})", cid, e),
+                None => format!("No album or song found for {}", cid),
+            };
+            eprintln!("{}", utils::format_failure_message(&message));
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+fn print_album_info(album: &Album) {
+    println!("Album: {} [{}]", album.name, album.cid);
+    if let Some(belong) = &album.belong {
+        println!("Belongs To: {}", belong);
+    }
+    let artistes = album.get_artistes();
+    if !artistes.is_empty() {
+        println!("Artists: {}", artistes.join(", "));
+    }
+    if let Some(intro) = &album.intro {
+        println!("Introduction:\n{}", intro);
+    }
+    println!("Tracks:");
+    for (index, song) in album.get_songs().iter().enumerate() {
+        println!("  {:02}. {} [{}]", index + 1, song.name, song.cid);
+    }
+}
+
+fn print_song_info(song: &Song) {
+    println!("Song: {} [{}]", song.name, song.cid);
+    if let Some(album_cid) = &song.album_cid {
+        println!("Album: {}", album_cid);
+    }
+    let artists = song.get_artists();
+    if !artists.is_empty() {
+        println!("Artists: {}", artists.join(", "));
+    }
+    if let Some(source_url) = &song.source_url {
+        println!("Source: {}", source_url);
+    }
+    if let Some(lyric_url) = &song.lyric_url {
+        println!("Lyrics: {}", lyric_url);
+    }
+    if let Some(mv_url) = &song.mv_url {
+        println!("MV: {}", mv_url);
+    }
+}
+
+fn print_sync_report(report: &SyncReport) {
+    for (label, items) in [
+        ("Added album", &report.added_albums),
+        ("Added song", &report.added_songs),
+        ("Updated song", &report.updated_songs),
+        ("Retried song", &report.retried_songs),
+    ] {
+        for item in items {
+            println!("{}: {}", label, item);
+        }
+    }
+
+    println!(
+        "Sync: {} albums added, {} songs added, {} songs updated, {} songs retried, {} albums and {} songs unchanged",
+        report.added_albums.len(),
+        report.added_songs.len(),
+        report.updated_songs.len(),
+        report.retried_songs.len(),
+        report.unchanged_albums,
+        report.unchanged_songs
+    );
+}
+
+fn print_plan(plan: &Plan) {
+    if plan.is_empty() {
+        println!("Nothing to do");
+        return;
+    }
+
+    println!("{:<10}  {:>10}  TARGET", "ACTION", "SIZE");
+    for action in &plan.actions {
+        let size = match action {
+            PlannedAction::Download {
+                size: Some(size), ..
+            } => format_size(*size),
+            PlannedAction::Download { size: None, .. } => "?".to_string(),
+            _ => String::new(),
+        };
+        let target = match action {
+            PlannedAction::Rename { from, to } => {
+                format!("{} -> {}", from.display(), to.display())
+            }
+            PlannedAction::Download { url, path, .. } => {
+                format!("{} <- {}", path.display(), url)
+            }
+            action => action.path().display().to_string(),
+        };
+        println!("{:<10}  {:>10}  {}", action.as_str(), size, target);
+    }
+
+    let downloads = plan.downloads().count();
+    let unknown = plan
+        .downloads()
+        .filter(|action| matches!(action, PlannedAction::Download { size: None, .. }))
+        .count();
+    print!(
+        "{} actions, {} downloads totalling {}",
+        plan.actions.len(),
+        downloads,
+        format_size(plan.total_bytes())
+    );
+    if unknown > 0 {
+        print!(" plus {} of unknown size", unknown);
+    }
+    println!();
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn print_diff(diff: &CatalogDiff) {
+    if diff.is_empty() {
+        println!("No differences");
+        return;
+    }
+
+    for (label, items) in [
+        ("Added album", &diff.added_albums),
+        ("Removed album", &diff.removed_albums),
+        ("Added song", &diff.added_songs),
+        ("Removed song", &diff.removed_songs),
+    ] {
+        for item in items {
+            println!("{}: [{}] {}", label, item.cid, item.name);
+        }
+    }
+    for rename in &diff.renamed_songs {
+        println!(
+            "Renamed song: [{}] {} -> {}",
+            rename.cid, rename.old_name, rename.new_name
+        );
+    }
+    for change in &diff.changed_artists {
+        println!(
+            "Changed artists: [{}] {}: {} -> {}",
+            change.song_cid.as_ref().unwrap_or(&change.album_cid),
+            change.name,
+            change.old_artists.join(", "),
+            change.new_artists.join(", ")
+        );
+    }
+    for change in &diff.changed_source_urls {
+        println!(
+            "Changed source URL: [{}] {}: {} -> {}",
+            change.song_cid,
+            change.name,
+            change.old_url.as_deref().unwrap_or("-"),
+            change.new_url.as_deref().unwrap_or("-")
+        );
+    }
+}
 
-    println!("Monster Siren Downloader v{}", version.unwrap_or("dev"));
-    println!("Starting Monster Siren Records music library download...");
+/// Prints the run summary and every failure, writes the JSON report when
+/// requested and picks the exit code.
+async fn finish_run(summary: &DownloadSummary, cli: &Cli) -> Result<ExitCode> {
+    for failure in &summary.failures {
+        let item = match (&failure.song_name, &failure.song_cid) {
+            (Some(name), Some(cid)) => format!(
+                "[{}] {} / [{}] {}",
+                failure.album_cid, failure.album_name, cid, name
+            ),
+            _ => format!("[{}] {}", failure.album_cid, failure.album_name),
+        };
+        println!(
+            "{}",
+            utils::format_failure_message(&format!(
+                "✗ {} {}: {} ({}, {} attempts){}",
+                failure.stage.as_str(),
+                item,
+                failure.message,
+                failure.error_kind,
+                failure.attempts,
+                failure
+                    .url
+                    .as_ref()
+                    .map(|url| format!(" <{}>", url))
+                    .unwrap_or_default()
+            ))
+        );
+    }
 
-    let client = MonsterSirenClient::new(version)?;
-    let downloader = Downloader::new(client);
+    if let Some(report_path) = &cli.global.report {
+        summary.save_json(report_path).await?;
+        println!("Run report written to {}", report_path.display());
+    }
 
-    downloader.download_all_tracks().await?;
+    println!(
+        "Albums: {} completed, {} failed; tracks: {} completed, {} failed",
+        summary.albums_completed,
+        summary.albums_failed,
+        summary.tracks_completed,
+        summary.tracks_failed
+    );
 
-    println!("All downloads completed!");
-    Ok(())
+    if summary.has_failures() {
+        println!(
+            "{}",
+            utils::format_failure_message("Downloads finished with failures.")
+        );
+        Ok(ExitCode::from(EXIT_PARTIAL_FAILURE))
+    } else {
+        println!("All downloads completed!");
+        Ok(ExitCode::SUCCESS)
+    }
 }
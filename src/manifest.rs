@@ -0,0 +1,229 @@
+use crate::{
+    Result,
+    library::STATE_DIR,
+    models::{Album, Song},
+    utils,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    Audio,
+    Lyrics,
+    Cover,
+    DetailedCover,
+    MusicVideo,
+    MusicVideoPoster,
+}
+
+/// A file written into the library by the downloader.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub album_cid: String,
+    pub song_cid: Option<String>,
+    pub kind: FileKind,
+    pub source_url: String,
+    /// Path relative to the library root, using `/` as separator.
+    pub path: String,
+    /// Size and hash of the content as downloaded, before any tags were
+    /// written to it.
+    pub size: u64,
+    pub sha256: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Unix timestamp in seconds.
+    pub downloaded_at: u64,
+}
+
+/// Catalog state of an album at the time it was downloaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlbumRecord {
+    pub name: String,
+}
+
+/// Catalog state of a song at the time it was downloaded, used by sync to
+/// detect changed sources.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SongRecord {
+    pub album_cid: String,
+    pub name: String,
+    pub source_url: Option<String>,
+    pub lyric_url: Option<String>,
+    #[serde(default)]
+    pub mv_url: Option<String>,
+    /// Some of the song's files failed to download, so sync fetches it again.
+    #[serde(default)]
+    pub incomplete: bool,
+}
+
+/// Record of every file the tool produced, stored in the library root.
+///
+/// Files present in the library but missing from the manifest were put
+/// there by something else and are left alone.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    version: u32,
+    #[serde(skip)]
+    root: PathBuf,
+    files: BTreeMap<String, ManifestEntry>,
+    #[serde(default)]
+    albums: BTreeMap<String, AlbumRecord>,
+    #[serde(default)]
+    songs: BTreeMap<String, SongRecord>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            root: PathBuf::new(),
+            files: BTreeMap::new(),
+            albums: BTreeMap::new(),
+            songs: BTreeMap::new(),
+        }
+    }
+}
+
+impl Manifest {
+    pub async fn load<P: AsRef<Path>>(library_root: P) -> Result<Self> {
+        let root = library_root.as_ref().to_path_buf();
+        let path = root.join(STATE_DIR).join(MANIFEST_FILE);
+
+        let mut manifest: Manifest = if utils::file_exists(&path) {
+            let content = tokio::fs::read(&path).await?;
+            serde_json::from_slice(&content)?
+        } else {
+            Manifest::default()
+        };
+        manifest.root = root;
+        Ok(manifest)
+    }
+
+    pub async fn save(&self) -> Result<()> {
+        let state_dir = self.root.join(STATE_DIR);
+        utils::ensure_dir_exists(&state_dir).await?;
+
+        let path = state_dir.join(MANIFEST_FILE);
+        let temp_path = state_dir.join(format!("{}.tmp", MANIFEST_FILE));
+        tokio::fs::write(&temp_path, serde_json::to_vec_pretty(self)?).await?;
+        tokio::fs::rename(temp_path, path).await?;
+        Ok(())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Converts an absolute or root-relative path into a manifest key.
+    pub fn relative_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+
+    pub fn record(&mut self, entry: ManifestEntry) {
+        self.files.insert(entry.path.clone(), entry);
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&ManifestEntry> {
+        self.files.get(&self.relative_path(path))
+    }
+
+    pub fn remove(&mut self, path: &Path) -> Option<ManifestEntry> {
+        let key = self.relative_path(path);
+        self.files.remove(&key)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.files.values()
+    }
+
+    pub fn album_entries<'a>(
+        &'a self,
+        album_id: &'a str,
+    ) -> impl Iterator<Item = &'a ManifestEntry> + 'a {
+        self.files
+            .values()
+            .filter(move |entry| entry.album_cid == album_id)
+    }
+
+    pub fn song_entries<'a>(
+        &'a self,
+        song_id: &'a str,
+    ) -> impl Iterator<Item = &'a ManifestEntry> + 'a {
+        self.files
+            .values()
+            .filter(move |entry| entry.song_cid.as_deref() == Some(song_id))
+    }
+
+    pub fn record_album(&mut self, album: &Album) {
+        self.albums.insert(
+            album.cid.clone(),
+            AlbumRecord {
+                name: album.name.clone(),
+            },
+        );
+    }
+
+    pub fn record_song(&mut self, album_id: &str, song: &Song) {
+        self.songs.insert(
+            song.cid.clone(),
+            SongRecord {
+                album_cid: album_id.to_string(),
+                name: song.name.clone(),
+                source_url: song.source_url.clone(),
+                lyric_url: song.lyric_url.clone(),
+                mv_url: song.mv_url.clone(),
+                incomplete: false,
+            },
+        );
+    }
+
+    /// Marks a song whose download failed. A song recorded before keeps its
+    /// old URLs, so a source change is still picked up by the next sync.
+    pub fn record_failed_song(&mut self, album_id: &str, song: &Song) {
+        match self.songs.get_mut(&song.cid) {
+            Some(record) => record.incomplete = true,
+            None => {
+                self.record_song(album_id, song);
+                if let Some(record) = self.songs.get_mut(&song.cid) {
+                    record.incomplete = true;
+                }
+            }
+        }
+    }
+
+    pub fn album(&self, album_id: &str) -> Option<&AlbumRecord> {
+        self.albums.get(album_id)
+    }
+
+    pub fn song(&self, song_id: &str) -> Option<&SongRecord> {
+        self.songs.get(song_id)
+    }
+
+    /// Rewrites the paths of every entry inside the directory `from` after it
+    /// was renamed to `to`.
+    pub fn rename_dir(&mut self, from: &str, to: &str) {
+        let prefix = format!("{}/", from);
+        let moved: Vec<String> = self
+            .files
+            .keys()
+            .filter(|key| key.starts_with(&prefix))
+            .cloned()
+            .collect();
+
+        for key in moved {
+            if let Some(mut entry) = self.files.remove(&key) {
+                entry.path = format!("{}/{}", to, &key[prefix.len()..]);
+                self.files.insert(entry.path.clone(), entry);
+            }
+        }
+    }
+}
@@ -1,20 +1,173 @@
 use crate::{
     Error, Result,
+    lyrics::Lyrics,
     models::{Album, Song},
 };
+use lofty::TextEncoding;
+use lofty::id3::v2::{SyncTextContentType, SynchronizedTextFrame, TimestampFormat};
 use lofty::picture::{MimeType, Picture, PictureType};
 use lofty::prelude::*;
 use lofty::probe::Probe;
-use lofty::tag::{Tag, TagExt};
+use lofty::tag::{ItemValue, Tag, TagExt, TagItem, TagType};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::Path;
 
-pub struct MetadataWriter;
+/// Custom tag holding the Monster Siren album cid.
+pub const ALBUM_ID_TAG: &str = "MSR_ALBUM_ID";
+/// Custom tag holding the Monster Siren song cid.
+pub const SONG_ID_TAG: &str = "MSR_SONG_ID";
+/// Custom tag holding the song's source URL, for tags without a standard
+/// field for it. ID3v2 uses `WOAS` instead.
+pub const SOURCE_URL_TAG: &str = "MSR_SOURCE_URL";
+
+/// When the tool writes one of the tag fields it owns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagPolicy {
+    /// Overwrite whatever the file has.
+    #[default]
+    Always,
+    /// Only fill the field in when the file has no value for it.
+    OnlyIfEmpty,
+    /// Leave the field alone.
+    Never,
+}
+
+impl TagPolicy {
+    fn allows(self, has_value: bool) -> bool {
+        match self {
+            TagPolicy::Always => true,
+            TagPolicy::OnlyIfEmpty => !has_value,
+            TagPolicy::Never => false,
+        }
+    }
+}
+
+/// A [`TagPolicy`] for each field the tool writes. Every other field, such
+/// as ratings, play counts or ReplayGain values, is always kept.
+///
+/// ```toml
+/// [tags]
+/// comment = "only_if_empty"
+/// genre = "never"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TagPolicies {
+    pub title: TagPolicy,
+    pub album: TagPolicy,
+    pub artist: TagPolicy,
+    pub album_artist: TagPolicy,
+    /// Track number and total.
+    pub track: TagPolicy,
+    /// Disc number and total. Albums on the site are always a single disc.
+    pub disc: TagPolicy,
+    pub genre: TagPolicy,
+    pub grouping: TagPolicy,
+    pub comment: TagPolicy,
+    /// The front cover picture.
+    pub cover: TagPolicy,
+    pub lyrics: TagPolicy,
+    /// The album and song cids.
+    pub catalog_ids: TagPolicy,
+    pub source_url: TagPolicy,
+}
+
+/// The genre and grouping tags written for an album's `belong` value.
+/// A missing value removes that tag when its policy is `always`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenreMapping {
+    pub genre: Option<String>,
+    /// Franchise or series, written as the grouping (`TIT1`, `GROUPING`,
+    /// `©grp`) tag.
+    pub grouping: Option<String>,
+}
+
+/// Maps album `belong` values to genres, on top of the built-in mappings.
+///
+/// ```toml
+/// [genres.belongs.arknights]
+/// genre = "Game Soundtrack"
+/// grouping = "Arknights"
+///
+/// [genres.fallback]
+/// genre = "Hypergryph"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenreMap {
+    /// Mappings by `belong` value, compared case-insensitively. These
+    /// replace the built-in mapping for the same value.
+    pub belongs: BTreeMap<String, GenreMapping>,
+    /// Used for albums whose `belong` value isn't mapped. Writes no genre
+    /// unless set.
+    pub fallback: GenreMapping,
+}
+
+impl GenreMap {
+    /// The mapping for `belong`, falling back to the built-in ones and then
+    /// to `fallback`.
+    pub fn get(&self, belong: Option<&str>) -> GenreMapping {
+        let Some(belong) = belong else {
+            return self.fallback.clone();
+        };
+        self.belongs
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(belong))
+            .map(|(_, mapping)| mapping.clone())
+            .or_else(|| builtin_genre(belong))
+            .unwrap_or_else(|| self.fallback.clone())
+    }
+}
+
+fn builtin_genre(belong: &str) -> Option<GenreMapping> {
+    let franchise = match belong.to_ascii_lowercase().as_str() {
+        "arknights" => "Arknights",
+        "endfield" => "Arknights: Endfield",
+        "popucom" => "POPUCOM",
+        _ => return None,
+    };
+    Some(GenreMapping {
+        genre: Some(franchise.to_string()),
+        grouping: Some(franchise.to_string()),
+    })
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MetadataWriter {
+    policies: TagPolicies,
+    genres: GenreMap,
+    force_retag: bool,
+}
 
 impl MetadataWriter {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    pub fn policies(mut self, policies: TagPolicies) -> Self {
+        self.policies = policies;
+        self
     }
 
+    pub fn genres(mut self, genres: GenreMap) -> Self {
+        self.genres = genres;
+        self
+    }
+
+    /// Clear the existing tags before writing and ignore the policies, as
+    /// tagging used to work before it merged.
+    pub fn force_retag(mut self, enabled: bool) -> Self {
+        self.force_retag = enabled;
+        self
+    }
+
+    /// Merges the song and album details, the album cover and, when given,
+    /// the lyrics into the tags of `file_path`, following the field
+    /// policies.
+    #[allow(clippy::too_many_arguments)]
     pub async fn write_metadata(
         &self,
         file_path: &Path,
@@ -23,6 +176,7 @@ impl MetadataWriter {
         track_number: u32,
         total_tracks: u32,
         album_cover_path: Option<&Path>,
+        lyrics: Option<&Lyrics>,
     ) -> Result<()> {
         let mut tagged_file = Probe::open(file_path)
             .map_err(|e| Error::File(format!("Failed to probe audio file: {}", e)))?
@@ -35,41 +189,100 @@ impl MetadataWriter {
         }
 
         let tag = tagged_file.tag_mut(tag_type).unwrap();
-        tag.clear();
+        if self.force_retag {
+            tag.clear();
+        }
 
-        tag.set_title(song.name.clone());
-        tag.set_album(album.name.clone());
+        if self.allows(self.policies.title, tag, &ItemKey::TrackTitle) {
+            tag.set_title(song.name.clone());
+        }
+        if self.allows(self.policies.album, tag, &ItemKey::AlbumTitle) {
+            tag.set_album(album.name.clone());
+        }
 
         let artists = song.get_artists();
-        if !artists.is_empty() {
+        if !artists.is_empty() && self.allows(self.policies.artist, tag, &ItemKey::TrackArtist) {
             tag.set_artist(artists.join(", "));
         }
 
-        tag.set_track(track_number);
-        tag.set_track_total(total_tracks);
+        let album_artists = album.get_artistes();
+        if !album_artists.is_empty()
+            && self.allows(self.policies.album_artist, tag, &ItemKey::AlbumArtist)
+        {
+            tag.insert_text(ItemKey::AlbumArtist, album_artists.join(", "));
+        }
+
+        if self.allows(self.policies.track, tag, &ItemKey::TrackNumber) {
+            tag.set_track(track_number);
+            tag.set_track_total(total_tracks);
+        }
 
-        if let Some(intro) = &album.intro {
+        if self.allows(self.policies.disc, tag, &ItemKey::DiscNumber) {
+            tag.set_disk(1);
+            tag.set_disk_total(1);
+        }
+
+        if let Some(intro) = &album.intro
+            && self.allows(self.policies.comment, tag, &ItemKey::Comment)
+        {
             tag.set_comment(intro.clone());
         }
 
-        if let Some(belong) = &album.belong {
-            match belong.as_str() {
-                "arknights" => tag.set_genre("Arknights".to_string()),
-                _ => tag.set_genre("Unknown Genre".to_string()),
+        // An unmapped genre or grouping is removed so that one written by
+        // an earlier mapping doesn't linger.
+        let mapping = self.genres.get(album.belong.as_deref());
+        for (value, policy, key) in [
+            (mapping.genre, self.policies.genre, ItemKey::Genre),
+            (
+                mapping.grouping,
+                self.policies.grouping,
+                ItemKey::ContentGroup,
+            ),
+        ] {
+            match value {
+                Some(value) if self.allows(policy, tag, &key) => {
+                    tag.insert_text(key, value);
+                }
+                None if self.policy(policy) == TagPolicy::Always => tag.remove_key(&key),
+                _ => {}
             }
         }
 
-        if let Some(cover_path) = album_cover_path {
-            if let Ok(cover_data) = std::fs::read(cover_path) {
-                let mime_type = self.get_image_mime_type(cover_path);
-                let picture = Picture::new_unchecked(
-                    PictureType::CoverFront,
-                    Some(mime_type),
-                    None,
-                    cover_data,
-                );
-                tag.set_picture(0, picture);
-            }
+        let has_cover = tag
+            .pictures()
+            .iter()
+            .any(|picture| picture.pic_type() == PictureType::CoverFront);
+        if let Some(cover_path) = album_cover_path
+            && self.policy(self.policies.cover).allows(has_cover)
+            && let Ok(cover_data) = std::fs::read(cover_path)
+        {
+            let mime_type = self.get_image_mime_type(cover_path);
+            let picture =
+                Picture::new_unchecked(PictureType::CoverFront, Some(mime_type), None, cover_data);
+            tag.remove_picture_type(PictureType::CoverFront);
+            tag.push_picture(picture);
+        }
+
+        if let Some(lyrics) = lyrics.filter(|lyrics| !lyrics.is_empty())
+            && self.allows(self.policies.lyrics, tag, &ItemKey::Lyrics)
+        {
+            self.set_lyrics(tag, lyrics)?;
+        }
+
+        let album_id_key = custom_key(tag.tag_type(), ALBUM_ID_TAG);
+        if self.allows(self.policies.catalog_ids, tag, &album_id_key) {
+            tag.insert_unchecked(TagItem::new(
+                album_id_key,
+                ItemValue::Text(album.cid.clone()),
+            ));
+            tag.insert_unchecked(TagItem::new(
+                custom_key(tag.tag_type(), SONG_ID_TAG),
+                ItemValue::Text(song.cid.clone()),
+            ));
+        }
+
+        if let Some(source_url) = &song.source_url {
+            self.set_source_url(tag, source_url);
         }
 
         tagged_file
@@ -79,6 +292,67 @@ impl MetadataWriter {
         Ok(())
     }
 
+    fn policy(&self, policy: TagPolicy) -> TagPolicy {
+        if self.force_retag {
+            TagPolicy::Always
+        } else {
+            policy
+        }
+    }
+
+    /// Whether `policy` lets the field stored under `key` be written.
+    fn allows(&self, policy: TagPolicy, tag: &Tag, key: &ItemKey) -> bool {
+        self.policy(policy).allows(tag.get(key).is_some())
+    }
+
+    /// Writes `source_url` as an ID3v2 `WOAS` frame, or a custom tag in
+    /// other formats.
+    fn set_source_url(&self, tag: &mut Tag, source_url: &str) {
+        let item = if tag.tag_type() == TagType::Id3v2 {
+            TagItem::new(
+                ItemKey::AudioSourceUrl,
+                ItemValue::Locator(source_url.to_string()),
+            )
+        } else {
+            TagItem::new(
+                custom_key(tag.tag_type(), SOURCE_URL_TAG),
+                ItemValue::Text(source_url.to_string()),
+            )
+        };
+        if self.allows(self.policies.source_url, tag, item.key()) {
+            tag.insert_unchecked(item);
+        }
+    }
+
+    /// Writes unsynchronised lyrics (ID3v2 `USLT`, Vorbis `LYRICS`, MP4
+    /// `©lyr`, ...) and, for ID3v2, synchronised lyrics as a `SYLT` frame
+    /// with millisecond timestamps.
+    fn set_lyrics(&self, tag: &mut Tag, lyrics: &Lyrics) -> Result<()> {
+        tag.insert_text(ItemKey::Lyrics, lyrics.text());
+
+        if tag.tag_type() == TagType::Id3v2
+            && let Some(lines) = lyrics.synced_lines()
+        {
+            let frame = SynchronizedTextFrame::new(
+                TextEncoding::UTF8,
+                *b"XXX",
+                TimestampFormat::MS,
+                SyncTextContentType::Lyrics,
+                None,
+                lines,
+            );
+            let data = frame
+                .as_bytes()
+                .map_err(|e| Error::File(format!("Failed to encode lyrics: {}", e)))?;
+            // Unknown keys are written as raw frames with that id.
+            tag.insert_unchecked(TagItem::new(
+                ItemKey::Unknown("SYLT".to_string()),
+                ItemValue::Binary(data),
+            ));
+        }
+        Ok(())
+    }
+
     fn get_image_mime_type(&self, image_path: &Path) -> MimeType {
         match image_path.extension().and_then(|ext| ext.to_str()) {
             Some("jpg") | Some("jpeg") => MimeType::Jpeg,
@@ -89,3 +363,12 @@ impl MetadataWriter {
         }
     }
 }
+
+/// The key for a custom text field called `name`: a `TXXX` frame in ID3v2,
+/// a freeform `----` atom in MP4 and a plain field elsewhere.
+fn custom_key(tag_type: TagType, name: &str) -> ItemKey {
+    match tag_type {
+        TagType::Mp4Ilst => ItemKey::Unknown(format!("----:com.apple.iTunes:{}", name)),
+        _ => ItemKey::Unknown(name.to_string()),
+    }
+}
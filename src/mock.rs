@@ -0,0 +1,440 @@
+//! A local stand-in for the Monster Siren website, serving the catalog API
+//! and files from fixtures, with injectable faults.
+//!
+//! ```no_run
+//! # async fn example() -> msr_downloader::Result<()> {
+//! use msr_downloader::mock::{Fault, MockServer};
+//!
+//! let server = MockServer::start().await?;
+//! server.add_file("/files/track.wav", b"RIFF...".to_vec());
+//! server.inject_fault("/api/albums", Fault::Status(500), 1);
+//! let base_url = server.url("");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    Result,
+    models::{Album, Song},
+};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// A failure the server produces instead of a normal response.
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// Respond with this HTTP status and an empty body.
+    Status(u16),
+    /// Respond with this HTTP status and a `Retry-After` header in seconds.
+    StatusRetryAfter(u16, u64),
+    /// Wait before sending the normal response.
+    Delay(Duration),
+    /// Announce the full `Content-Length` but close the connection after this
+    /// many body bytes.
+    Truncate(usize),
+    /// Respond to an API request with `code != 0` and this message.
+    ApiError(String),
+    /// Answer with a `206` holding the file from this offset, whatever range
+    /// was requested.
+    RangeFrom(usize),
+}
+
+#[derive(Default)]
+struct MockState {
+    albums: Vec<Album>,
+    songs: HashMap<String, Song>,
+    files: HashMap<String, Vec<u8>>,
+    faults: HashMap<String, VecDeque<Fault>>,
+    requests: HashMap<String, usize>,
+}
+
+/// Local HTTP server serving `/api/albums`, `/api/album/{cid}/detail`,
+/// `/api/album/{cid}/data`, `/api/song/{cid}`, `/api/songs` and any files
+/// added with [`MockServer::add_file`]. API responses carry an `ETag` and
+/// honour `If-None-Match`; files honour `Range` and `If-Range`.
+///
+/// The server stops when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Binds to a free port on localhost and starts serving.
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState::default()));
+
+        let server_state = Arc::clone(&state);
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = Arc::clone(&server_state);
+                tokio::spawn(async move {
+                    let _ = handle_connection(stream, state).await;
+                });
+            }
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    /// Absolute URL of `path` on this server.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// Adds an album as the newest release, listed before the ones already
+    /// added. Its songs should carry full details. Like the real site, the
+    /// album list only returns each album's cid, name, cover and artists,
+    /// and the album detail endpoint only its songs' cid, name and artists.
+    pub fn add_album(&self, album: Album) {
+        let mut state = self.state.lock().unwrap();
+        for song in album.get_songs() {
+            state.songs.insert(song.cid.clone(), song);
+        }
+        state.albums.insert(0, album);
+    }
+
+    /// Replaces the details of a song that was added with an album.
+    pub fn update_song(&self, song: Song) {
+        let mut state = self.state.lock().unwrap();
+        state.songs.insert(song.cid.clone(), song);
+    }
+
+    pub fn add_file<B: Into<Vec<u8>>>(&self, path: &str, content: B) {
+        let mut state = self.state.lock().unwrap();
+        state.files.insert(path.to_string(), content.into());
+    }
+
+    /// Makes the next `times` requests to `path` fail with `fault`.
+    pub fn inject_fault(&self, path: &str, fault: Fault, times: usize) {
+        let mut state = self.state.lock().unwrap();
+        let faults = state.faults.entry(path.to_string()).or_default();
+        faults.extend(std::iter::repeat_n(fault, times));
+    }
+
+    /// Number of requests received for `path`.
+    pub fn request_count(&self, path: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        state.requests.get(path).copied().unwrap_or_default()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+    truncate_at: Option<usize>,
+}
+
+impl Response {
+    fn new(status: u16, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body,
+            truncate_at: None,
+        }
+    }
+
+    fn json(value: Value) -> Self {
+        let body = value.to_string().into_bytes();
+        let etag = etag(&body);
+        let mut response = Self::new(200, body);
+        response
+            .headers
+            .push(("Content-Type", "application/json".to_string()));
+        response.headers.push(("ETag", etag));
+        response
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn etag(content: &[u8]) -> String {
+    format!("\"{:x}\"", Sha256::digest(content))
+}
+
+async fn handle_connection(mut stream: TcpStream, state: Arc<Mutex<MockState>>) -> Result<()> {
+    let Some(request) = read_request(&mut stream).await? else {
+        return Ok(());
+    };
+
+    let fault = {
+        let mut state = state.lock().unwrap();
+        *state.requests.entry(request.path.clone()).or_default() += 1;
+        state
+            .faults
+            .get_mut(&request.path)
+            .and_then(|faults| faults.pop_front())
+    };
+
+    let response = match fault {
+        Some(Fault::Status(status)) => Response::new(status, Vec::new()),
+        Some(Fault::StatusRetryAfter(status, seconds)) => {
+            let mut response = Response::new(status, Vec::new());
+            response.headers.push(("Retry-After", seconds.to_string()));
+            response
+        }
+        Some(Fault::ApiError(message)) => {
+            Response::json(json!({ "code": 1, "msg": message, "data": null }))
+        }
+        Some(Fault::Delay(delay)) => {
+            tokio::time::sleep(delay).await;
+            respond(&request, &state.lock().unwrap())
+        }
+        Some(Fault::Truncate(bytes)) => {
+            let mut response = respond(&request, &state.lock().unwrap());
+            response.truncate_at = Some(bytes);
+            response
+        }
+        Some(Fault::RangeFrom(start)) => match state.lock().unwrap().files.get(&request.path) {
+            Some(content) => {
+                let mut response = Response::new(206, content[start..].to_vec());
+                response.headers.push((
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, content.len() - 1, content.len()),
+                ));
+                response.headers.push(("ETag", etag(content)));
+                response
+            }
+            None => Response::new(404, Vec::new()),
+        },
+        None => respond(&request, &state.lock().unwrap()),
+    };
+
+    let send_body = request.method != "HEAD";
+    write_response(&mut stream, response, send_body).await
+}
+
+async fn read_request(stream: &mut TcpStream) -> Result<Option<Request>> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 1024];
+    while !buffer.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let text = String::from_utf8_lossy(&buffer);
+    let mut lines = text.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Ok(None);
+    };
+    let path = target.split('?').next().unwrap_or(target).to_string();
+
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    Ok(Some(Request {
+        method: method.to_string(),
+        path,
+        headers,
+    }))
+}
+
+/// Routes the request and answers `304 Not Modified` when its
+/// `If-None-Match` matches the response's `ETag`.
+fn respond(request: &Request, state: &MockState) -> Response {
+    let response = route(request, state);
+    let etag = response.header("ETag");
+    if response.status == 200
+        && etag.is_some()
+        && request.headers.get("if-none-match").map(String::as_str) == etag
+    {
+        let mut not_modified = Response::new(304, Vec::new());
+        not_modified
+            .headers
+            .push(("ETag", etag.unwrap_or_default().to_string()));
+        return not_modified;
+    }
+    response
+}
+
+fn route(request: &Request, state: &MockState) -> Response {
+    let path = request.path.as_str();
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+    match segments.as_slice() {
+        ["api", "albums"] => {
+            let albums: Vec<Album> = state
+                .albums
+                .iter()
+                .map(|album| Album {
+                    intro: None,
+                    belong: None,
+                    cover_de_url: None,
+                    songs: None,
+                    ..album.clone()
+                })
+                .collect();
+            api_response(json!(albums))
+        }
+        ["api", "album", album_id, endpoint @ ("detail" | "data")] => {
+            let album = state
+                .albums
+                .iter()
+                .find(|album| album.cid == *album_id)
+                .map(|album| {
+                    let songs = (*endpoint == "detail").then(|| {
+                        album
+                            .get_songs()
+                            .into_iter()
+                            .map(|song| listing_song(&song))
+                            .collect()
+                    });
+                    Album {
+                        songs,
+                        ..album.clone()
+                    }
+                });
+            api_response(json!(album))
+        }
+        ["api", "song", song_id] => api_response(json!(state.songs.get(*song_id))),
+        ["api", "songs"] => {
+            let list: Vec<Song> = state
+                .albums
+                .iter()
+                .flat_map(|album| album.get_songs())
+                .map(|song| listing_song(&song))
+                .collect();
+            api_response(json!({ "list": list, "autoplay": "" }))
+        }
+        _ => match state.files.get(path) {
+            Some(content) => file_response(request, content),
+            None => Response::new(404, Vec::new()),
+        },
+    }
+}
+
+/// A song as it appears in album details and the song list, without URLs.
+fn listing_song(song: &Song) -> Song {
+    Song {
+        source_url: None,
+        lyric_url: None,
+        mv_url: None,
+        mv_cover_url: None,
+        ..song.clone()
+    }
+}
+
+fn api_response(data: Value) -> Response {
+    Response::json(json!({ "code": 0, "msg": "", "data": data }))
+}
+
+fn file_response(request: &Request, content: &[u8]) -> Response {
+    let etag = etag(content);
+    let total = content.len();
+
+    let range_start = request
+        .headers
+        .get("range")
+        .and_then(|range| range.strip_prefix("bytes="))
+        .and_then(|range| range.strip_suffix('-'))
+        .and_then(|start| start.parse::<usize>().ok());
+    let validator_matches = request
+        .headers
+        .get("if-range")
+        .is_none_or(|validator| *validator == etag);
+
+    let mut response = match range_start {
+        Some(start) if start >= total => {
+            let mut response = Response::new(416, Vec::new());
+            response
+                .headers
+                .push(("Content-Range", format!("bytes */{}", total)));
+            return response;
+        }
+        Some(start) if validator_matches => {
+            let mut response = Response::new(206, content[start..].to_vec());
+            response.headers.push((
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, total - 1, total),
+            ));
+            response
+        }
+        _ => Response::new(200, content.to_vec()),
+    };
+
+    response.headers.push(("ETag", etag));
+    response
+        .headers
+        .push(("Accept-Ranges", "bytes".to_string()));
+    response
+}
+
+/// Writes `response`, leaving out the body (but not its `Content-Length`)
+/// when answering a `HEAD` request.
+async fn write_response(stream: &mut TcpStream, response: Response, send_body: bool) -> Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes()).await?;
+    let body = match response.truncate_at {
+        _ if !send_body => &[][..],
+        Some(bytes) => &response.body[..bytes.min(response.body.len())],
+        None => &response.body[..],
+    };
+    stream.write_all(body).await?;
+    stream.flush().await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        206 => "Partial Content",
+        304 => "Not Modified",
+        404 => "Not Found",
+        416 => "Range Not Satisfiable",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
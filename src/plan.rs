@@ -0,0 +1,88 @@
+use crate::manifest::FileKind;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A single change a download would make to the library.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlannedAction {
+    /// Move an album folder to its current name.
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    CreateDir {
+        path: PathBuf,
+    },
+    WriteInfo {
+        path: PathBuf,
+    },
+    /// Fetch `url` into `path`. `size` comes from a `HEAD` request and is
+    /// `None` when the server didn't report it.
+    Download {
+        url: String,
+        path: PathBuf,
+        kind: FileKind,
+        size: Option<u64>,
+    },
+    /// Write tags into an audio file.
+    Tag {
+        path: PathBuf,
+    },
+}
+
+impl PlannedAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlannedAction::Rename { .. } => "rename",
+            PlannedAction::CreateDir { .. } => "create_dir",
+            PlannedAction::WriteInfo { .. } => "write_info",
+            PlannedAction::Download { .. } => "download",
+            PlannedAction::Tag { .. } => "tag",
+        }
+    }
+
+    /// The path the action writes to.
+    pub fn path(&self) -> &Path {
+        match self {
+            PlannedAction::Rename { to: path, .. }
+            | PlannedAction::CreateDir { path }
+            | PlannedAction::WriteInfo { path }
+            | PlannedAction::Download { path, .. }
+            | PlannedAction::Tag { path } => path,
+        }
+    }
+}
+
+/// Everything a download would do, in the order it would do it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Plan {
+    pub actions: Vec<PlannedAction>,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    pub fn push(&mut self, action: PlannedAction) {
+        self.actions.push(action);
+    }
+
+    pub fn downloads(&self) -> impl Iterator<Item = &PlannedAction> {
+        self.actions
+            .iter()
+            .filter(|action| matches!(action, PlannedAction::Download { .. }))
+    }
+
+    /// Sum of the known download sizes.
+    pub fn total_bytes(&self) -> u64 {
+        self.actions
+            .iter()
+            .filter_map(|action| match action {
+                PlannedAction::Download { size, .. } => *size,
+                _ => None,
+            })
+            .sum()
+    }
+}
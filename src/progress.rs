@@ -1,14 +1,36 @@
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const MAX_DOWNLOAD_BARS: usize = 5;
 
 pub struct ProgressTracker {
     multi_progress: Arc<MultiProgress>,
+    /// Bytes downloaded during the run, created with the first download.
+    total_bytes: Mutex<Option<ProgressBar>>,
+    /// Slots for per-file bars, so no more are shown than files can be
+    /// downloaded at once.
+    download_bars: Arc<Semaphore>,
+}
+
+impl Default for ProgressTracker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ProgressTracker {
     pub fn new() -> Self {
+        Self::with_max_download_bars(MAX_DOWNLOAD_BARS)
+    }
+
+    /// Shows at most `max` per-file bars at a time; files started while all
+    /// of them are taken only count towards the total-bytes bar.
+    pub fn with_max_download_bars(max: usize) -> Self {
         Self {
             multi_progress: Arc::new(MultiProgress::new()),
+            total_bytes: Mutex::new(None),
+            download_bars: Arc::new(Semaphore::new(max)),
         }
     }
 
@@ -40,6 +62,76 @@ impl ProgressTracker {
         pb
     }
 
+    fn create_total_bytes_bar(&self) -> ProgressBar {
+        let pb = self.multi_progress.add(ProgressBar::new(0));
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] {bytes}/{total_bytes} ({bytes_per_sec}) {msg}")
+                .unwrap(),
+        );
+        pb.set_message("Total downloaded");
+        pb.enable_steady_tick(std::time::Duration::from_millis(100));
+        pb
+    }
+
+    /// Starts tracking the download of `filename`, which continues from
+    /// `offset` bytes already on disk. `content_length` is the number of
+    /// bytes still to come, when known.
+    pub fn start_download(
+        &self,
+        filename: &str,
+        offset: u64,
+        content_length: Option<u64>,
+    ) -> DownloadProgress {
+        let total = self
+            .total_bytes
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| self.create_total_bytes_bar())
+            .clone();
+        if let Some(content_length) = content_length {
+            total.inc_length(content_length);
+        }
+
+        let permit = self.download_bars.clone().try_acquire_owned().ok();
+        let bar = permit.as_ref().map(|_| {
+            let pb = self.create_download_progress_bar(offset, filename);
+            match content_length {
+                Some(content_length) => pb.inc_length(content_length),
+                None => pb.unset_length(),
+            }
+            pb.set_position(offset);
+            pb
+        });
+
+        DownloadProgress {
+            multi_progress: Arc::clone(&self.multi_progress),
+            bar,
+            total,
+            content_length,
+            received: 0,
+            finished: false,
+            _permit: permit,
+        }
+    }
+
+    /// Bytes downloaded since the total-bytes bar was started.
+    pub fn downloaded_bytes(&self) -> u64 {
+        self.total_bytes
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, ProgressBar::position)
+    }
+
+    /// Finishes the total-bytes bar. The next download starts a new one.
+    pub fn finish_downloads(&self) {
+        if let Some(total) = self.total_bytes.lock().unwrap().take() {
+            total.finish();
+            self.multi_progress.remove(&total);
+        }
+    }
+
     pub fn remove_progress_bar(&self, pb: &ProgressBar) {
         self.multi_progress.remove(pb);
     }
@@ -52,3 +144,53 @@ impl ProgressTracker {
         self.println(&format!(">>> {}", message));
     }
 }
+
+/// Progress of a single file download, reported to its own bar (if it got
+/// one) and the run's total-bytes bar. A download dropped before
+/// [`finish`](Self::finish) takes its bytes back out of the total, so
+/// retries aren't counted twice.
+pub struct DownloadProgress {
+    multi_progress: Arc<MultiProgress>,
+    bar: Option<ProgressBar>,
+    total: ProgressBar,
+    content_length: Option<u64>,
+    received: u64,
+    finished: bool,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl DownloadProgress {
+    /// Whether the file got a bar of its own.
+    pub fn has_bar(&self) -> bool {
+        self.bar.is_some()
+    }
+
+    pub fn inc(&mut self, bytes: u64) {
+        self.received += bytes;
+        self.total.inc(bytes);
+        if let Some(bar) = &self.bar {
+            bar.inc(bytes);
+        }
+    }
+
+    pub fn finish(mut self) {
+        if self.content_length.is_none() {
+            self.total.inc_length(self.received);
+        }
+        self.finished = true;
+    }
+}
+
+impl Drop for DownloadProgress {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.total.dec(self.received);
+            self.total
+                .dec_length(self.content_length.unwrap_or_default());
+        }
+        if let Some(bar) = self.bar.take() {
+            bar.finish_and_clear();
+            self.multi_progress.remove(&bar);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{
+    Error, Result,
+    models::{Album, Song},
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Step of the download pipeline a failure happened in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureStage {
+    AlbumDetails,
+    SongDetails,
+    Cover,
+    AlbumInfo,
+    Track,
+    Lyrics,
+    Tagging,
+    MusicVideo,
+}
+
+impl FailureStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureStage::AlbumDetails => "album details",
+            FailureStage::SongDetails => "song details",
+            FailureStage::Cover => "cover",
+            FailureStage::AlbumInfo => "album info",
+            FailureStage::Track => "track",
+            FailureStage::Lyrics => "lyrics",
+            FailureStage::Tagging => "tagging",
+            FailureStage::MusicVideo => "music video",
+        }
+    }
+}
+
+/// A single item that could not be fetched or written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Failure {
+    pub stage: FailureStage,
+    pub album_cid: String,
+    pub album_name: String,
+    pub song_cid: Option<String>,
+    pub song_name: Option<String>,
+    pub url: Option<String>,
+    pub error_kind: String,
+    pub message: String,
+    pub attempts: u32,
+}
+
+impl Failure {
+    pub fn new(
+        stage: FailureStage,
+        album: &Album,
+        song: Option<&Song>,
+        url: Option<&str>,
+        error: &Error,
+    ) -> Self {
+        Self {
+            stage,
+            album_cid: album.cid.clone(),
+            album_name: album.name.clone(),
+            song_cid: song.map(|song| song.cid.clone()),
+            song_name: song.map(|song| song.name.clone()),
+            url: url.map(str::to_string),
+            error_kind: error.kind().to_string(),
+            message: error.to_string(),
+            attempts: error.attempts(),
+        }
+    }
+}
+
+/// Counts of what a download run processed, plus every failure along the
+/// way.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DownloadSummary {
+    pub albums_completed: usize,
+    pub albums_failed: usize,
+    pub tracks_completed: usize,
+    pub tracks_failed: usize,
+    pub failures: Vec<Failure>,
+}
+
+impl DownloadSummary {
+    pub fn has_failures(&self) -> bool {
+        self.albums_failed > 0 || self.tracks_failed > 0 || !self.failures.is_empty()
+    }
+
+    pub fn merge(&mut self, other: DownloadSummary) {
+        self.albums_completed += other.albums_completed;
+        self.albums_failed += other.albums_failed;
+        self.tracks_completed += other.tracks_completed;
+        self.tracks_failed += other.tracks_failed;
+        self.failures.extend(other.failures);
+    }
+
+    pub async fn save_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        tokio::fs::write(path, serde_json::to_vec_pretty(self)?).await?;
+        Ok(())
+    }
+
+    pub async fn load_json<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = tokio::fs::read(path).await?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{Error, Result};
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How often and how patiently failed requests are retried.
+///
+/// Only transient failures are retried: connection errors, timeouts,
+/// interrupted bodies, HTTP 429 and 5xx. A `Retry-After` header sent by the
+/// server takes precedence over the computed backoff, up to `max_backoff`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Randomise each delay between half and all of the computed backoff so
+    /// concurrent downloads don't retry in lockstep.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before the attempt following failed attempt number `attempt`
+    /// (starting at 1).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+
+        if self.jitter && !delay.is_zero() {
+            rand::rng().random_range(delay / 2..=delay)
+        } else {
+            delay
+        }
+    }
+
+    /// Runs `operation` until it succeeds, fails with a non-retryable error
+    /// or runs out of attempts. Errors after more than one attempt are
+    /// wrapped in [`Error::RetriesExhausted`].
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(e) if e.is_retryable() && attempt < self.max_attempts => {
+                    let delay = e.retry_after().map_or_else(
+                        || self.backoff(attempt),
+                        |delay| delay.min(self.max_backoff),
+                    );
+                    log::warn!(
+                        "Attempt {}/{} failed: {}; retrying in {:.1}s",
+                        attempt,
+                        self.max_attempts,
+                        e,
+                        delay.as_secs_f32()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) if attempt > 1 => {
+                    return Err(Error::RetriesExhausted {
+                        attempts: attempt,
+                        source: Box::new(e),
+                    });
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{
+    Error, Result,
+    fixture::{FixtureSource, file_response},
+    models::{Album, Song},
+    source::{CatalogSource, FileResponse, ResumeFrom},
+    utils,
+};
+use futures::{StreamExt, TryStreamExt, stream};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+const CATALOG_FILE: &str = "catalog.json";
+const FILES_DIR: &str = "files";
+
+/// The full catalog at one point in time: every album with its detailed
+/// songs, newest release first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogSnapshot {
+    pub version: u32,
+    /// Unix timestamp in seconds.
+    pub created_at: u64,
+    pub albums: Vec<Album>,
+}
+
+impl CatalogSnapshot {
+    pub fn new(albums: Vec<Album>) -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            created_at: utils::unix_timestamp(),
+            albums,
+        }
+    }
+
+    /// Fetches every album with its songs' full details from `source`,
+    /// making at most `max_requests` requests at a time: first the album
+    /// details, then the details of all their songs. Any failed or missing
+    /// album or song fails the whole snapshot, since a partial one would
+    /// show up as removals when diffed.
+    pub async fn fetch<S: CatalogSource>(source: &S, max_requests: usize) -> Result<Self> {
+        let max_requests = max_requests.max(1);
+
+        let albums: Vec<Album> = stream::iter(source.get_albums().await?)
+            .map(|album| async move {
+                source
+                    .get_album_with_songs(&album.cid)
+                    .await?
+                    .ok_or_else(|| {
+                        Error::InvalidData(format!("Album {} has no details", album.cid))
+                    })
+            })
+            .buffered(max_requests)
+            .try_collect()
+            .await?;
+
+        let mut songs = stream::iter(albums.iter().flat_map(Album::get_songs))
+            .map(|song| async move {
+                source
+                    .get_song(&song.cid)
+                    .await?
+                    .ok_or_else(|| Error::InvalidData(format!("Song {} has no details", song.cid)))
+            })
+            .buffered(max_requests)
+            .try_collect::<Vec<Song>>()
+            .await?
+            .into_iter();
+
+        let albums = albums
+            .into_iter()
+            .map(|album| {
+                let count = album.get_songs().len();
+                Album {
+                    songs: Some(songs.by_ref().take(count).collect()),
+                    ..album
+                }
+            })
+            .collect();
+
+        Ok(Self::new(albums))
+    }
+
+    pub async fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = tokio::fs::read(path).await?;
+        let snapshot: CatalogSnapshot = serde_json::from_slice(&content)?;
+        if snapshot.version > SNAPSHOT_VERSION {
+            return Err(Error::InvalidData(format!(
+                "Unsupported snapshot version {}",
+                snapshot.version
+            )));
+        }
+        Ok(snapshot)
+    }
+
+    pub async fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        tokio::fs::write(path, serde_json::to_vec_pretty(self)?).await?;
+        Ok(())
+    }
+}
+
+/// Catalog read from a snapshot directory on disk.
+///
+/// The directory holds `catalog.json` with a [`CatalogSnapshot`] and a
+/// `files/` tree mirroring the URLs it references as `files/<host>/<path>`.
+/// [`SnapshotSource::create`] (`snapshot --with-files` on the command line)
+/// builds one.
+pub struct SnapshotSource {
+    root: PathBuf,
+    catalog: FixtureSource,
+}
+
+impl SnapshotSource {
+    pub async fn open<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let snapshot = CatalogSnapshot::load(root.join(CATALOG_FILE)).await?;
+        let catalog = snapshot
+            .albums
+            .into_iter()
+            .fold(FixtureSource::new(), FixtureSource::with_album);
+
+        Ok(Self { root, catalog })
+    }
+
+    /// Builds a snapshot directory at `root` from `source`: the catalog as
+    /// [`CatalogSnapshot::fetch`] takes it, plus every cover, track, lyric
+    /// and video it references, making at most `max_requests` requests at a
+    /// time. Files already in the directory are kept, so an interrupted run
+    /// can be continued. `catalog.json` is written last.
+    pub async fn create<S: CatalogSource, P: AsRef<Path>>(
+        source: &S,
+        root: P,
+        max_requests: usize,
+    ) -> Result<CatalogSnapshot> {
+        let root = root.as_ref();
+        let max_requests = max_requests.max(1);
+        let snapshot = CatalogSnapshot::fetch(source, max_requests).await?;
+
+        let urls: BTreeSet<&str> = snapshot
+            .albums
+            .iter()
+            .flat_map(|album| {
+                let songs = album.songs.iter().flatten().flat_map(|song| {
+                    [
+                        &song.source_url,
+                        &song.lyric_url,
+                        &song.mv_url,
+                        &song.mv_cover_url,
+                    ]
+                });
+                [&album.cover_url, &album.cover_de_url]
+                    .into_iter()
+                    .chain(songs)
+            })
+            .filter_map(Option::as_deref)
+            .collect();
+
+        stream::iter(urls)
+            .map(|url| save_file(source, root, url))
+            .buffer_unordered(max_requests)
+            .try_collect::<()>()
+            .await?;
+
+        snapshot.save(root.join(CATALOG_FILE)).await?;
+        Ok(snapshot)
+    }
+
+    /// Where the file for `url` is stored inside a snapshot directory.
+    pub fn file_path(root: &Path, url: &str) -> Option<PathBuf> {
+        let url = url::Url::parse(url).ok()?;
+        let mut path = root
+            .join(FILES_DIR)
+            .join(utils::sanitize_filename(url.host_str()?));
+        for segment in url.path_segments()? {
+            if segment.is_empty() || segment == ".." {
+                continue;
+            }
+            path.push(utils::sanitize_filename(segment));
+        }
+        Some(path)
+    }
+}
+
+/// Downloads `url` from `source` into the snapshot directory at `root`,
+/// unless it is already there.
+async fn save_file<S: CatalogSource>(source: &S, root: &Path, url: &str) -> Result<()> {
+    let path = SnapshotSource::file_path(root, url)
+        .ok_or_else(|| Error::InvalidData(format!("Unsupported file URL: {}", url)))?;
+    if utils::file_exists(&path) {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        utils::ensure_dir_exists(parent).await?;
+    }
+
+    let mut response = source.fetch_file(url, None).await?;
+    let mut temp_path = path.clone().into_os_string();
+    temp_path.push(".tmp");
+    let mut file = tokio::fs::File::create(&temp_path).await?;
+    while let Some(chunk) = response.body.next().await {
+        file.write_all(&chunk?).await?;
+    }
+    file.flush().await?;
+    tokio::fs::rename(&temp_path, &path).await?;
+    Ok(())
+}
+
+impl CatalogSource for SnapshotSource {
+    async fn get_albums(&self) -> Result<Vec<Album>> {
+        self.catalog.get_albums().await
+    }
+
+    async fn get_album_with_songs(&self, album_id: &str) -> Result<Option<Album>> {
+        self.catalog.get_album_with_songs(album_id).await
+    }
+
+    async fn get_song(&self, song_id: &str) -> Result<Option<Song>> {
+        self.catalog.get_song(song_id).await
+    }
+
+    async fn get_songs(&self) -> Result<Vec<Song>> {
+        self.catalog.get_songs().await
+    }
+
+    async fn fetch_file(&self, url: &str, resume: Option<ResumeFrom<'_>>) -> Result<FileResponse> {
+        let not_found = || Error::HttpStatus {
+            url: url.to_string(),
+            status: 404,
+            retry_after: None,
+        };
+
+        let path = Self::file_path(&self.root, url).ok_or_else(not_found)?;
+        if !utils::file_exists(&path) {
+            return Err(not_found());
+        }
+
+        let metadata = tokio::fs::metadata(&path).await?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let etag = format!("\"{}-{}\"", metadata.len(), modified);
+
+        let content = tokio::fs::read(&path).await?;
+        Ok(file_response(content.into(), etag, None, resume))
+    }
+
+    async fn file_size(&self, url: &str) -> Result<Option<u64>> {
+        let Some(path) = Self::file_path(&self.root, url) else {
+            return Ok(None);
+        };
+        if !utils::file_exists(&path) {
+            return Ok(None);
+        }
+        Ok(Some(tokio::fs::metadata(&path).await?.len()))
+    }
+}
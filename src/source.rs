@@ -0,0 +1,62 @@
+use crate::{
+    Result,
+    models::{Album, Song},
+};
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use std::future::Future;
+
+/// Body and validators of a fetched file.
+pub struct FileResponse {
+    /// Start offset when the body continues a partial download, `None` when
+    /// it holds the whole file.
+    pub partial_from: Option<u64>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_length: Option<u64>,
+    pub body: BoxStream<'static, Result<Bytes>>,
+}
+
+/// Where partial downloads continue from, and the validator (ETag or
+/// Last-Modified value) recorded when they started.
+#[derive(Debug, Clone, Copy)]
+pub struct ResumeFrom<'a> {
+    pub offset: u64,
+    pub validator: &'a str,
+}
+
+/// A catalog of albums and songs plus the files they reference.
+///
+/// [`Downloader`](crate::Downloader) only talks to its catalog through this
+/// trait, so it can run against the Monster Siren API, an in-memory
+/// [`FixtureSource`](crate::FixtureSource) or an on-disk
+/// [`SnapshotSource`](crate::SnapshotSource).
+pub trait CatalogSource: Send + Sync {
+    /// All albums, newest release first. Songs may be missing.
+    fn get_albums(&self) -> impl Future<Output = Result<Vec<Album>>> + Send;
+
+    /// An album with its track list.
+    fn get_album_with_songs(
+        &self,
+        album_id: &str,
+    ) -> impl Future<Output = Result<Option<Album>>> + Send;
+
+    /// Full details of a song, including its source and lyric URLs.
+    fn get_song(&self, song_id: &str) -> impl Future<Output = Result<Option<Song>>> + Send;
+
+    /// All songs. Entries may lack URLs.
+    fn get_songs(&self) -> impl Future<Output = Result<Vec<Song>>> + Send;
+
+    /// Fetches a file referenced by the catalog. When `resume` is given and
+    /// the file is unchanged, only the bytes from `resume.offset` on are
+    /// returned.
+    fn fetch_file(
+        &self,
+        url: &str,
+        resume: Option<ResumeFrom<'_>>,
+    ) -> impl Future<Output = Result<FileResponse>> + Send;
+
+    /// Size in bytes of a file referenced by the catalog, without fetching
+    /// it. `None` when the size isn't known in advance.
+    fn file_size(&self, url: &str) -> impl Future<Output = Result<Option<u64>>> + Send;
+}
@@ -0,0 +1,141 @@
+use crate::{
+    filter::Filter,
+    manifest::Manifest,
+    models::{Album, Song},
+    report::DownloadSummary,
+};
+use std::collections::{HashMap, HashSet};
+
+/// An album the sync needs to visit. `songs` is `None` when the whole album
+/// is new, otherwise it holds the cids of the songs to fetch.
+#[derive(Debug, Clone)]
+pub struct PlannedAlbum {
+    pub album: Album,
+    pub songs: Option<HashSet<String>>,
+}
+
+/// Difference between the catalog listing and the library manifest.
+#[derive(Debug, Default)]
+pub struct SyncPlan {
+    pub albums: Vec<PlannedAlbum>,
+    pub added_albums: Vec<String>,
+    pub added_songs: Vec<String>,
+    pub updated_songs: Vec<String>,
+    /// Known songs whose download failed on an earlier run.
+    pub retried_songs: Vec<String>,
+    /// Details of the known songs whose source or lyric URL changed. Their
+    /// old files are replaced once the new ones are downloaded.
+    pub replaced_songs: Vec<Song>,
+    pub unchanged_albums: usize,
+    pub unchanged_songs: usize,
+}
+
+/// What a sync found and what the resulting downloads did.
+#[derive(Debug, Clone, Default)]
+pub struct SyncReport {
+    pub added_albums: Vec<String>,
+    pub added_songs: Vec<String>,
+    pub updated_songs: Vec<String>,
+    pub retried_songs: Vec<String>,
+    pub unchanged_albums: usize,
+    pub unchanged_songs: usize,
+    pub download: DownloadSummary,
+}
+
+impl SyncPlan {
+    /// Compares the album and song listings against what the manifest
+    /// recorded, leaving out the songs the filter excludes. Only the fields
+    /// present in `songs` are compared: the song listing lacks URLs, so the
+    /// caller passes the details of the songs already in the library.
+    pub fn new(albums: &[Album], songs: &[Song], manifest: &Manifest, filter: &Filter) -> Self {
+        let mut songs_by_album: HashMap<&str, Vec<&Song>> = HashMap::new();
+        for song in songs {
+            if let Some(album_id) = song.album_cid.as_deref() {
+                songs_by_album.entry(album_id).or_default().push(song);
+            }
+        }
+
+        let mut plan = SyncPlan::default();
+
+        for album in albums {
+            let album_songs: Vec<&Song> = songs_by_album
+                .remove(album.cid.as_str())
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|song| filter.song_allowed(album, song))
+                .collect();
+
+            if manifest.album(&album.cid).is_none() {
+                plan.added_albums.push(describe(&album.name, &album.cid));
+                plan.added_songs.extend(
+                    album_songs
+                        .iter()
+                        .map(|song| describe(&song.name, &song.cid)),
+                );
+                plan.albums.push(PlannedAlbum {
+                    album: album.clone(),
+                    songs: None,
+                });
+                continue;
+            }
+
+            let mut changed = HashSet::new();
+            for song in album_songs {
+                match manifest.song(&song.cid) {
+                    None => {
+                        plan.added_songs.push(describe(&song.name, &song.cid));
+                        changed.insert(song.cid.clone());
+                    }
+                    Some(record) => {
+                        let source_changed = song
+                            .source_url
+                            .as_ref()
+                            .is_some_and(|url| record.source_url.as_ref() != Some(url));
+                        let lyric_changed = song
+                            .lyric_url
+                            .as_ref()
+                            .is_some_and(|url| record.lyric_url.as_ref() != Some(url));
+
+                        if source_changed || lyric_changed {
+                            plan.updated_songs.push(describe(&song.name, &song.cid));
+                            plan.replaced_songs.push(song.clone());
+                            changed.insert(song.cid.clone());
+                        } else if record.incomplete {
+                            plan.retried_songs.push(describe(&song.name, &song.cid));
+                            changed.insert(song.cid.clone());
+                        } else {
+                            plan.unchanged_songs += 1;
+                        }
+                    }
+                }
+            }
+
+            if changed.is_empty() {
+                plan.unchanged_albums += 1;
+            } else {
+                plan.albums.push(PlannedAlbum {
+                    album: album.clone(),
+                    songs: Some(changed),
+                });
+            }
+        }
+
+        plan
+    }
+
+    pub fn report(&self) -> SyncReport {
+        SyncReport {
+            added_albums: self.added_albums.clone(),
+            added_songs: self.added_songs.clone(),
+            updated_songs: self.updated_songs.clone(),
+            retried_songs: self.retried_songs.clone(),
+            unchanged_albums: self.unchanged_albums,
+            unchanged_songs: self.unchanged_songs,
+            download: DownloadSummary::default(),
+        }
+    }
+}
+
+fn describe(name: &str, cid: &str) -> String {
+    format!("[{}] {}", cid, name)
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use common::{add_album, fast_retries};
+use msr_downloader::mock::{Fault, MockServer};
+use msr_downloader::{Error, MonsterSirenClient, ResponseCache};
+use std::path::Path;
+use std::time::Duration;
+
+fn client(server: &MockServer, cache: ResponseCache) -> MonsterSirenClient {
+    MonsterSirenClient::builder()
+        .base_url(server.url(""))
+        .retry_policy(fast_retries())
+        .cache(cache)
+        .build()
+        .unwrap()
+}
+
+fn cache(dir: &Path, ttl: Duration) -> ResponseCache {
+    ResponseCache::new(dir).with_ttl(ttl)
+}
+
+#[tokio::test]
+async fn serves_fresh_responses_from_cache() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+
+    let client = client(&server, cache(dir.path(), Duration::from_secs(3600)));
+    client.get_album_with_songs("1001").await.unwrap().unwrap();
+    client.get_song("100101").await.unwrap().unwrap();
+    client.get_album_with_songs("1001").await.unwrap().unwrap();
+    client.get_song("100101").await.unwrap().unwrap();
+
+    assert_eq!(server.request_count("/api/album/1001/detail"), 1);
+    assert_eq!(server.request_count("/api/song/100101"), 1);
+}
+
+#[tokio::test]
+async fn always_revalidates_the_listings() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+
+    let client = client(&server, cache(dir.path(), Duration::from_secs(3600)));
+    assert_eq!(client.get_albums().await.unwrap().len(), 1);
+    assert_eq!(client.get_songs().await.unwrap().0.len(), 1);
+    add_album(&server, "1002", "Second Album", 1);
+    assert_eq!(client.get_albums().await.unwrap().len(), 2);
+    assert_eq!(client.get_songs().await.unwrap().0.len(), 2);
+
+    assert_eq!(server.request_count("/api/albums"), 2);
+    assert_eq!(server.request_count("/api/songs"), 2);
+}
+
+#[tokio::test]
+async fn revalidates_stale_responses() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+
+    let client = client(&server, cache(dir.path(), Duration::ZERO));
+    assert_eq!(client.get_albums().await.unwrap().len(), 1);
+    assert_eq!(client.get_albums().await.unwrap().len(), 1);
+    add_album(&server, "1002", "Second Album", 1);
+    assert_eq!(client.get_albums().await.unwrap().len(), 2);
+
+    assert_eq!(server.request_count("/api/albums"), 3);
+}
+
+#[tokio::test]
+async fn offline_mode_never_touches_the_network() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+
+    client(&server, cache(dir.path(), Duration::ZERO))
+        .get_album_with_songs("1001")
+        .await
+        .unwrap();
+
+    let offline = client(
+        &server,
+        cache(dir.path(), Duration::ZERO).with_offline(true),
+    );
+    let album = offline.get_album_with_songs("1001").await.unwrap().unwrap();
+    assert_eq!(album.get_songs().len(), 1);
+    assert!(matches!(
+        offline.get_albums().await,
+        Err(Error::Offline { .. })
+    ));
+    assert!(matches!(
+        offline
+            .download_file(&server.url("/files/1001/cover.jpg"))
+            .await,
+        Err(Error::Offline { .. })
+    ));
+
+    assert_eq!(server.request_count("/api/album/1001/detail"), 1);
+    assert_eq!(server.request_count("/api/albums"), 0);
+    assert_eq!(server.request_count("/files/1001/cover.jpg"), 0);
+}
+
+#[tokio::test]
+async fn does_not_cache_api_errors() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    server.inject_fault("/api/albums", Fault::ApiError("busy".to_string()), 1);
+    let dir = tempfile::tempdir().unwrap();
+
+    let client = client(&server, cache(dir.path(), Duration::from_secs(3600)));
+    assert!(matches!(client.get_albums().await, Err(Error::Api { .. })));
+    assert_eq!(client.get_albums().await.unwrap().len(), 1);
+
+    assert_eq!(server.request_count("/api/albums"), 2);
+}
+
+#[tokio::test]
+async fn keeps_urls_apart_by_port_and_full_name() {
+    let first = MockServer::start().await.unwrap();
+    let second = MockServer::start().await.unwrap();
+    add_album(&first, "1001", "First Album", 1);
+    add_album(&second, "1001", "Other Album", 1);
+    add_album(&first, "1.a", "Dotted Album", 1);
+    add_album(&first, "1.b", "Dotted Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+    let ttl = Duration::from_secs(3600);
+
+    for (server, name) in [(&first, "First Album"), (&second, "Other Album")] {
+        let album = client(server, cache(dir.path(), ttl))
+            .get_album_with_songs("1001")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(album.name, name);
+    }
+
+    let client = client(&first, cache(dir.path(), ttl));
+    for cid in ["1.a", "1.b"] {
+        let song = client
+            .get_song(&format!("{}01", cid))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(song.album_cid.as_deref(), Some(cid));
+    }
+    assert_eq!(first.request_count("/api/song/1.b01"), 1);
+}
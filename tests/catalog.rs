@@ -0,0 +1,107 @@
+use msr_downloader::catalog::{MatchField, ResultKind};
+use msr_downloader::{Album, Catalog, Song};
+
+fn song(cid: &str, name: &str, artists: &[&str]) -> Song {
+    Song {
+        cid: cid.to_string(),
+        name: name.to_string(),
+        album_cid: None,
+        source_url: None,
+        lyric_url: None,
+        mv_url: None,
+        mv_cover_url: None,
+        artists: Some(artists.iter().map(|a| a.to_string()).collect()),
+        artistes: None,
+    }
+}
+
+fn album(cid: &str, name: &str, intro: &str, songs: Vec<Song>) -> Album {
+    Album {
+        cid: cid.to_string(),
+        name: name.to_string(),
+        intro: Some(intro.to_string()),
+        belong: Some("arknights".to_string()),
+        cover_url: None,
+        cover_de_url: None,
+        artistes: Some(vec!["塞壬唱片-MSR".to_string()]),
+        songs: Some(songs),
+    }
+}
+
+fn catalog() -> Catalog {
+    Catalog::new(vec![
+        album(
+            "0283",
+            "Speed of Light",
+            "A track for the summer event.",
+            vec![
+                song("697691", "Speed of Light", &["DJ Okawari"]),
+                song("697692", "Speed of Light (Instrumental)", &["DJ Okawari"]),
+            ],
+        ),
+        album(
+            "1012",
+            "生命之地",
+            "萨尔贡的旋律",
+            vec![song("880301", "生命之地", &["塞壬唱片-MSR", "袁娅维"])],
+        ),
+        album(
+            "1020",
+            "Lighthouse",
+            "Waves against the rocks.",
+            vec![song("880401", "Lighthouse", &["Adam Gubman"])],
+        ),
+    ])
+}
+
+fn cids(results: &[msr_downloader::SearchResult]) -> Vec<&str> {
+    results.iter().map(|result| result.cid.as_str()).collect()
+}
+
+#[test]
+fn ranks_exact_matches_first() {
+    let results = catalog().search("speed of light");
+
+    assert_eq!(cids(&results)[..3], ["0283", "697691", "697692"]);
+    assert_eq!(results[0].kind, ResultKind::Album);
+    assert_eq!(results[1].album_cid.as_deref(), Some("0283"));
+}
+
+#[test]
+fn ignores_case_and_full_width_forms() {
+    assert_eq!(cids(&catalog().search("LIGHTHOUSE")), ["1020", "880401"]);
+    assert_eq!(
+        cids(&catalog().search("ｌｉｇｈｔｈｏｕｓｅ")),
+        ["1020", "880401"]
+    );
+}
+
+#[test]
+fn matches_cjk_without_word_boundaries() {
+    assert_eq!(cids(&catalog().search("生命")), ["1012", "880301"]);
+    assert_eq!(cids(&catalog().search("袁娅维")), ["880301"]);
+    assert_eq!(cids(&catalog().search("生命 之地")), ["1012", "880301"]);
+}
+
+#[test]
+fn tolerates_typos() {
+    assert_eq!(cids(&catalog().search("lighthuose")), ["1020", "880401"]);
+    assert_eq!(cids(&catalog().search("okawri")), ["697691", "697692"]);
+}
+
+#[test]
+fn searches_artists_and_intros() {
+    let results = catalog().search("gubman");
+    assert_eq!(cids(&results), ["880401"]);
+    assert_eq!(results[0].field, MatchField::Artist);
+
+    let results = catalog().search("rocks");
+    assert_eq!(cids(&results), ["1020"]);
+    assert_eq!(results[0].field, MatchField::Intro);
+}
+
+#[test]
+fn requires_every_word_to_match() {
+    assert!(catalog().search("lighthouse okawari").is_empty());
+    assert!(catalog().search("   ").is_empty());
+}
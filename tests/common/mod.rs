@@ -0,0 +1,101 @@
+#![allow(dead_code)]
+
+use msr_downloader::mock::MockServer;
+use msr_downloader::{Album, Downloader, MonsterSirenClient, RetryPolicy, Song};
+use std::path::Path;
+use std::time::Duration;
+
+pub fn fast_retries() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(50),
+        jitter: false,
+    }
+}
+
+/// A silent mono 8 kHz WAV file that lofty can tag.
+pub fn wav_bytes(samples: u32) -> Vec<u8> {
+    let data_len = samples * 2;
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&8000u32.to_le_bytes());
+    wav.extend_from_slice(&16000u32.to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    wav.resize(wav.len() + data_len as usize, 0);
+    wav
+}
+
+/// Adds an album with `tracks` songs to the server, along with its cover,
+/// audio and lyric files.
+pub fn add_album(server: &MockServer, cid: &str, name: &str, tracks: usize) -> Album {
+    let album = album_fixture(server, cid, name, tracks);
+    server.add_album(album.clone());
+    album
+}
+
+/// Builds an album with `tracks` songs and adds its files to the server,
+/// leaving it to the caller to adjust and add the album itself.
+pub fn album_fixture(server: &MockServer, cid: &str, name: &str, tracks: usize) -> Album {
+    let cover_path = format!("/files/{}/cover.jpg", cid);
+    server.add_file(&cover_path, format!("cover of {}", cid));
+
+    let songs = (1..=tracks)
+        .map(|n| {
+            let song_id = format!("{}{:02}", cid, n);
+            let audio_path = format!("/files/{}/{}.wav", cid, song_id);
+            let lyric_path = format!("/files/{}/{}.lrc", cid, song_id);
+            server.add_file(&audio_path, wav_bytes(4000));
+            server.add_file(&lyric_path, format!("[00:00.00]{}\n", song_id));
+
+            Song {
+                cid: song_id,
+                name: format!("Song {}", n),
+                album_cid: Some(cid.to_string()),
+                source_url: Some(server.url(&audio_path)),
+                lyric_url: Some(server.url(&lyric_path)),
+                mv_url: None,
+                mv_cover_url: None,
+                artists: Some(vec!["塞壬唱片-MSR".to_string()]),
+                artistes: None,
+            }
+        })
+        .collect();
+
+    Album {
+        cid: cid.to_string(),
+        name: name.to_string(),
+        intro: Some(format!("Intro of {}", name)),
+        belong: Some("arknights".to_string()),
+        cover_url: Some(server.url(&cover_path)),
+        cover_de_url: None,
+        artistes: Some(vec!["塞壬唱片-MSR".to_string()]),
+        songs: Some(songs),
+    }
+}
+
+pub fn client(server: &MockServer) -> MonsterSirenClient {
+    MonsterSirenClient::builder()
+        .base_url(server.url(""))
+        .timeout(Duration::from_secs(2))
+        .retry_policy(fast_retries())
+        .build()
+        .unwrap()
+}
+
+pub fn downloader(server: &MockServer, save_path: &Path) -> Downloader {
+    let client = client(server);
+
+    Downloader::builder(client)
+        .save_path(save_path)
+        .retry_policy(fast_retries())
+        .build()
+}
@@ -0,0 +1,441 @@
+mod common;
+
+use common::{add_album, album_fixture, client, downloader, fast_retries, wav_bytes};
+use msr_downloader::mock::{Fault, MockServer};
+use msr_downloader::{
+    DownloadOptions, Downloader, ErrorPolicy, FailureStage, FileKind, Manifest, MonsterSirenClient,
+};
+use std::time::Duration;
+
+#[tokio::test]
+async fn downloads_whole_library() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 2);
+    add_album(&server, "1002", "Second Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+
+    let summary = downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    assert!(!summary.has_failures(), "{:?}", summary.failures);
+    assert_eq!(summary.albums_completed, 2);
+    assert_eq!(summary.tracks_completed, 3);
+
+    let first = dir.path().join("001 - First Album");
+    let second = dir.path().join("002 - Second Album");
+    for file in [
+        "01.Song 1.wav",
+        "01.Song 1.lrc",
+        "02.Song 2.wav",
+        "02.Song 2.lrc",
+        "info.txt",
+        "Album Cover.jpg",
+    ] {
+        assert!(first.join(file).exists(), "missing {}", file);
+    }
+    assert!(second.join("01.Song 1.wav").exists());
+
+    let info = std::fs::read_to_string(first.join("info.txt")).unwrap();
+    assert!(info.contains("Intro of First Album"));
+
+    let manifest = Manifest::load(dir.path()).await.unwrap();
+    assert!(manifest.album("1001").is_some());
+    assert!(manifest.song("100102").is_some());
+    let entry = manifest
+        .get(&first.join("01.Song 1.lrc"))
+        .expect("lyrics recorded in manifest");
+    assert_eq!(entry.song_cid.as_deref(), Some("100101"));
+    assert_eq!(entry.size, "[00:00.00]100101\n".len() as u64);
+}
+
+#[tokio::test]
+async fn treats_zero_concurrency_options_as_one() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 2);
+    let dir = tempfile::tempdir().unwrap();
+
+    let downloader = Downloader::builder(client(&server))
+        .options(DownloadOptions {
+            save_path: dir.path().to_path_buf(),
+            max_concurrent_downloads: 0,
+            max_concurrent_albums: 0,
+            max_concurrent_mv_downloads: 0,
+            retry_policy: fast_retries(),
+            ..DownloadOptions::default()
+        })
+        .build();
+    assert_eq!(downloader.options().max_concurrent_albums, 1);
+
+    let summary = tokio::time::timeout(Duration::from_secs(10), downloader.download_all_tracks())
+        .await
+        .expect("download finished")
+        .unwrap();
+    assert!(!summary.has_failures(), "{:?}", summary.failures);
+    assert_eq!(summary.tracks_completed, 2);
+}
+
+#[tokio::test]
+async fn retries_server_errors() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    server.inject_fault("/api/albums", Fault::Status(500), 1);
+    server.inject_fault("/files/1001/100101.wav", Fault::Status(503), 2);
+    let dir = tempfile::tempdir().unwrap();
+
+    let summary = downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    assert!(!summary.has_failures(), "{:?}", summary.failures);
+    assert_eq!(server.request_count("/api/albums"), 2);
+    assert_eq!(server.request_count("/files/1001/100101.wav"), 3);
+}
+
+#[tokio::test]
+async fn resumes_truncated_downloads() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    server.inject_fault("/files/1001/100101.wav", Fault::Truncate(1000), 1);
+    let dir = tempfile::tempdir().unwrap();
+
+    let summary = downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    assert!(!summary.has_failures(), "{:?}", summary.failures);
+    let manifest = Manifest::load(dir.path()).await.unwrap();
+    let entry = manifest
+        .get(&dir.path().join("001 - First Album/01.Song 1.wav"))
+        .unwrap();
+    assert_eq!(entry.size, wav_bytes(4000).len() as u64);
+}
+
+#[tokio::test]
+async fn restarts_downloads_when_the_range_does_not_match() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    server.inject_fault("/files/1001/100101.wav", Fault::Truncate(1000), 1);
+    server.inject_fault("/files/1001/100101.wav", Fault::RangeFrom(500), 1);
+    let dir = tempfile::tempdir().unwrap();
+
+    let summary = downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    assert!(!summary.has_failures(), "{:?}", summary.failures);
+    assert_eq!(server.request_count("/files/1001/100101.wav"), 3);
+    let manifest = Manifest::load(dir.path()).await.unwrap();
+    let entry = manifest
+        .get(&dir.path().join("001 - First Album/01.Song 1.wav"))
+        .unwrap();
+    assert_eq!(entry.size, wav_bytes(4000).len() as u64);
+}
+
+#[tokio::test]
+async fn retries_slow_responses_after_timeout() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    server.inject_fault(
+        "/api/album/1001/detail",
+        Fault::Delay(Duration::from_secs(5)),
+        1,
+    );
+    let dir = tempfile::tempdir().unwrap();
+
+    let summary = downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    assert!(!summary.has_failures(), "{:?}", summary.failures);
+    assert_eq!(server.request_count("/api/album/1001/detail"), 2);
+}
+
+#[tokio::test]
+async fn records_api_errors_and_continues() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    add_album(&server, "1002", "Second Album", 1);
+    server.inject_fault(
+        "/api/album/1001/detail",
+        Fault::ApiError("album unavailable".to_string()),
+        1,
+    );
+    let dir = tempfile::tempdir().unwrap();
+
+    let summary = downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    assert_eq!(summary.albums_completed, 1);
+    assert_eq!(summary.albums_failed, 1);
+    assert_eq!(summary.failures.len(), 1);
+    let failure = &summary.failures[0];
+    assert_eq!(failure.stage, FailureStage::AlbumDetails);
+    assert_eq!(failure.album_cid, "1001");
+    assert!(failure.message.contains("album unavailable"));
+
+    // API errors are not retried.
+    assert_eq!(server.request_count("/api/album/1001/detail"), 1);
+    assert!(dir.path().join("002 - Second Album/01.Song 1.wav").exists());
+}
+
+#[tokio::test]
+async fn records_manifest_save_errors_and_continues() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    add_album(&server, "1002", "Second Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+    // A directory in the way of the temporary file makes every save fail.
+    std::fs::create_dir_all(dir.path().join(".msr-downloader/manifest.json.tmp")).unwrap();
+
+    let summary = downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    assert_eq!(summary.albums_failed, 2);
+    assert_eq!(summary.tracks_completed, 2);
+    assert_eq!(summary.failures.len(), 2);
+    assert!(
+        summary
+            .failures
+            .iter()
+            .all(|failure| failure.stage == FailureStage::AlbumInfo)
+    );
+    assert!(dir.path().join("002 - Second Album/01.Song 1.wav").exists());
+}
+
+#[tokio::test]
+async fn fail_fast_stops_on_first_error() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    server.inject_fault("/files/1001/100101.wav", Fault::Status(404), 1);
+    let dir = tempfile::tempdir().unwrap();
+
+    let client = MonsterSirenClient::builder()
+        .base_url(server.url(""))
+        .retry_policy(fast_retries())
+        .build()
+        .unwrap();
+    let result = Downloader::builder(client)
+        .save_path(dir.path())
+        .error_policy(ErrorPolicy::FailFast)
+        .build()
+        .download_all_tracks()
+        .await;
+
+    // Track failures are per song and never abort the run; only album
+    // level failures do.
+    let summary = result.unwrap();
+    assert_eq!(summary.tracks_failed, 1);
+
+    server.inject_fault("/api/album/1001/detail", Fault::Status(404), 1);
+    let client = MonsterSirenClient::builder()
+        .base_url(server.url(""))
+        .retry_policy(fast_retries())
+        .build()
+        .unwrap();
+    let result = Downloader::builder(client)
+        .save_path(dir.path())
+        .error_policy(ErrorPolicy::FailFast)
+        .build()
+        .download_all_tracks()
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn sync_fetches_only_new_albums() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+
+    downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    add_album(&server, "1002", "Second Album", 2);
+    let report = downloader(&server, dir.path()).sync().await.unwrap();
+
+    assert_eq!(report.added_albums, vec!["[1002] Second Album"]);
+    assert_eq!(report.unchanged_albums, 1);
+    assert_eq!(report.download.tracks_completed, 2);
+    assert_eq!(server.request_count("/api/album/1001/detail"), 1);
+    assert_eq!(server.request_count("/files/1001/100101.wav"), 1);
+    assert!(dir.path().join("001 - First Album").exists());
+    assert!(dir.path().join("002 - Second Album/02.Song 2.wav").exists());
+}
+
+#[tokio::test]
+async fn sync_replaces_songs_once_their_new_source_downloads() {
+    let server = MockServer::start().await.unwrap();
+    let album = add_album(&server, "1001", "First Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+    downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+    let track = dir.path().join("001 - First Album/01.Song 1.wav");
+    let old_len = std::fs::metadata(&track).unwrap().len();
+
+    let new_path = "/files/1001/100101-remaster.wav";
+    server.add_file(new_path, wav_bytes(8000));
+    let mut song = album.get_songs().remove(0);
+    song.source_url = Some(server.url(new_path));
+    server.update_song(song);
+    server.inject_fault(new_path, Fault::Status(404), 1);
+
+    let report = downloader(&server, dir.path()).sync().await.unwrap();
+    assert_eq!(report.updated_songs, vec!["[100101] Song 1"]);
+    assert!(report.download.has_failures());
+    assert_eq!(std::fs::metadata(&track).unwrap().len(), old_len);
+    let manifest = Manifest::load(dir.path()).await.unwrap();
+    assert!(manifest.get(&track).is_some());
+
+    let report = downloader(&server, dir.path()).sync().await.unwrap();
+    assert_eq!(report.updated_songs, vec!["[100101] Song 1"]);
+    assert!(!report.download.has_failures(), "{:?}", report.download);
+    assert!(std::fs::metadata(&track).unwrap().len() > old_len);
+    assert!(
+        !dir.path()
+            .join("001 - First Album/01.Song 1.wav.old")
+            .exists()
+    );
+    let manifest = Manifest::load(dir.path()).await.unwrap();
+    assert_eq!(
+        manifest.get(&track).unwrap().source_url,
+        server.url(new_path)
+    );
+
+    let report = downloader(&server, dir.path()).sync().await.unwrap();
+    assert!(report.updated_songs.is_empty());
+    assert_eq!(report.unchanged_songs, 1);
+}
+
+#[tokio::test]
+async fn sync_retries_failed_songs_and_skips_filtered_ones() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 3);
+    server.inject_fault("/files/1001/100101.wav", Fault::Status(404), 2);
+    let dir = tempfile::tempdir().unwrap();
+    let filtered = || {
+        Downloader::builder(client(&server))
+            .save_path(dir.path())
+            .retry_policy(fast_retries())
+            .filter(toml::from_str("[exclude]\nsong_names = [\"Song 3\"]").unwrap())
+            .build()
+    };
+    filtered().download_all_tracks().await.unwrap();
+
+    let report = filtered().sync().await.unwrap();
+    assert!(report.added_songs.is_empty(), "{:?}", report.added_songs);
+    assert_eq!(report.retried_songs, vec!["[100101] Song 1"]);
+    assert_eq!(report.unchanged_songs, 1);
+
+    let report = filtered().sync().await.unwrap();
+    assert_eq!(report.retried_songs, vec!["[100101] Song 1"]);
+    assert!(!report.download.has_failures(), "{:?}", report.download);
+    assert!(dir.path().join("001 - First Album/01.Song 1.wav").exists());
+
+    let report = filtered().sync().await.unwrap();
+    assert!(report.retried_songs.is_empty());
+    assert_eq!(report.unchanged_songs, 2);
+}
+
+#[tokio::test]
+async fn downloads_music_videos_when_enabled() {
+    let server = MockServer::start().await.unwrap();
+    let mut album = album_fixture(&server, "1001", "First Album", 2);
+    let mut songs = album.songs.take().unwrap();
+    server.add_file("/files/1001/100101.mp4", "video of 100101");
+    server.add_file("/files/1001/100101-poster.png", "poster of 100101");
+    songs[0].mv_url = Some(server.url("/files/1001/100101.mp4"));
+    songs[0].mv_cover_url = Some(server.url("/files/1001/100101-poster.png"));
+    album.songs = Some(songs);
+    server.add_album(album);
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("001 - First Album");
+
+    downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+    assert!(!first.join("01.Song 1.mp4").exists());
+    assert_eq!(server.request_count("/files/1001/100101.mp4"), 0);
+
+    let summary = Downloader::builder(client(&server))
+        .save_path(dir.path())
+        .retry_policy(fast_retries())
+        .download_mvs(true)
+        .max_concurrent_mv_downloads(1)
+        .build()
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    assert!(!summary.has_failures(), "{:?}", summary.failures);
+    assert_eq!(
+        std::fs::read(first.join("01.Song 1.mp4")).unwrap(),
+        b"video of 100101"
+    );
+    assert!(first.join("01.Song 1-poster.png").exists());
+    assert!(!first.join("02.Song 2.mp4").exists());
+
+    let info = std::fs::read_to_string(first.join("info.txt")).unwrap();
+    assert_eq!(info.matches("Music Video: yes").count(), 1);
+
+    let manifest = Manifest::load(dir.path()).await.unwrap();
+    let entry = manifest
+        .get(&first.join("01.Song 1.mp4"))
+        .expect("music video recorded in manifest");
+    assert_eq!(entry.kind, FileKind::MusicVideo);
+    assert!(manifest.song("100101").unwrap().mv_url.is_some());
+    assert!(manifest.song("100102").unwrap().mv_url.is_none());
+}
+
+#[tokio::test]
+async fn sync_retries_songs_whose_music_video_failed() {
+    let server = MockServer::start().await.unwrap();
+    let mut album = album_fixture(&server, "1001", "First Album", 2);
+    let mut songs = album.songs.take().unwrap();
+    server.add_file("/files/1001/100101.mp4", "video of 100101");
+    server.add_file("/files/1001/100101-poster.png", "poster of 100101");
+    songs[0].mv_url = Some(server.url("/files/1001/100101.mp4"));
+    songs[0].mv_cover_url = Some(server.url("/files/1001/100101-poster.png"));
+    album.songs = Some(songs);
+    server.add_album(album);
+    server.inject_fault("/files/1001/100101-poster.png", Fault::Status(404), 1);
+    let dir = tempfile::tempdir().unwrap();
+    let with_mvs = || {
+        Downloader::builder(client(&server))
+            .save_path(dir.path())
+            .retry_policy(fast_retries())
+            .download_mvs(true)
+            .build()
+    };
+
+    let summary = with_mvs().download_all_tracks().await.unwrap();
+    assert_eq!(summary.failures.len(), 1, "{:?}", summary.failures);
+    assert_eq!(summary.failures[0].stage, FailureStage::MusicVideo);
+
+    let report = with_mvs().sync().await.unwrap();
+    assert_eq!(report.retried_songs, vec!["[100101] Song 1"]);
+    assert!(!report.download.has_failures(), "{:?}", report.download);
+    assert!(
+        dir.path()
+            .join("001 - First Album/01.Song 1-poster.png")
+            .exists()
+    );
+
+    let report = with_mvs().sync().await.unwrap();
+    assert!(report.retried_songs.is_empty());
+    assert_eq!(report.unchanged_songs, 2);
+}
@@ -0,0 +1,208 @@
+mod common;
+
+use common::{add_album, album_fixture, client, fast_retries};
+use msr_downloader::config::Config;
+use msr_downloader::mock::MockServer;
+use msr_downloader::{Album, Downloader, FileKind, Filter, Pattern, PlannedAction, Song};
+
+fn filter(toml: &str) -> Filter {
+    toml::from_str(toml).unwrap()
+}
+
+fn album(name: &str, belong: Option<&str>) -> Album {
+    Album {
+        cid: "1001".to_string(),
+        name: name.to_string(),
+        intro: None,
+        belong: belong.map(str::to_string),
+        cover_url: None,
+        cover_de_url: None,
+        artistes: Some(vec!["塞壬唱片-MSR".to_string()]),
+        songs: None,
+    }
+}
+
+fn song(name: &str, artists: &[&str]) -> Song {
+    Song {
+        cid: "100101".to_string(),
+        name: name.to_string(),
+        album_cid: Some("1001".to_string()),
+        source_url: None,
+        lyric_url: None,
+        mv_url: None,
+        mv_cover_url: None,
+        artists: Some(artists.iter().map(|a| a.to_string()).collect()),
+        artistes: None,
+    }
+}
+
+#[test]
+fn patterns_are_case_insensitive_globs_or_regexes() {
+    let glob = Pattern::new("*instrumental*").unwrap();
+    assert!(glob.is_match("Speed of Light (Instrumental)"));
+    assert!(!glob.is_match("Speed of Light"));
+    assert!(Pattern::new("Song ?").unwrap().is_match("song 1"));
+    assert!(!Pattern::new("Song").unwrap().is_match("Song 1"));
+
+    let regex = Pattern::new("re:^ost\\b").unwrap();
+    assert!(regex.is_match("OST Vol. 2"));
+    assert!(!regex.is_match("Lost"));
+    assert!(Pattern::new("re:(").is_err());
+}
+
+#[test]
+fn album_rules() {
+    let filter = filter(
+        r#"
+        [include]
+        album_names = ["*Light*"]
+        belongs = ["Arknights"]
+
+        [exclude]
+        albums = ["2002"]
+        "#,
+    );
+
+    assert_eq!(
+        filter.album_allowed(&album("Speed of Light", Some("arknights"))),
+        Some(true)
+    );
+    assert_eq!(
+        filter.album_allowed(&album("Speed of Light", Some("other"))),
+        Some(false)
+    );
+    assert_eq!(
+        filter.album_allowed(&album("Lighthouse", None)),
+        None,
+        "belong is unknown until the album details are fetched"
+    );
+    assert_eq!(
+        filter.album_allowed(&album("Radiance", Some("arknights"))),
+        Some(false)
+    );
+
+    let mut excluded = album("Speed of Light", Some("arknights"));
+    excluded.cid = "2002".to_string();
+    assert_eq!(filter.album_allowed(&excluded), Some(false));
+}
+
+#[test]
+fn song_rules() {
+    let filter = filter(
+        r#"
+        [include]
+        artists = ["DJ*"]
+
+        [exclude]
+        song_names = ["*(Instrumental)"]
+        "#,
+    );
+    let album = album("Speed of Light", None);
+
+    assert!(filter.song_allowed(&album, &song("Speed of Light", &["DJ Okawari"])));
+    assert!(!filter.song_allowed(
+        &album,
+        &song("Speed of Light (Instrumental)", &["DJ Okawari"])
+    ));
+    assert!(!filter.song_allowed(&album, &song("Speed of Light", &["Adam Gubman"])));
+    assert!(
+        !filter.song_allowed(&album, &song("Speed of Light", &[])),
+        "songs without artists fall back to the album's"
+    );
+}
+
+#[test]
+fn rejects_unknown_config_keys() {
+    assert!(toml::from_str::<Config>("[filter.include]\nalbum = [\"1\"]").is_err());
+}
+
+#[tokio::test]
+async fn excluded_albums_cost_no_detail_requests() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    add_album(&server, "1002", "Second Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+
+    let summary = Downloader::builder(client(&server))
+        .save_path(dir.path())
+        .retry_policy(fast_retries())
+        .filter(filter("[exclude]\nalbum_names = [\"first*\"]"))
+        .build()
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    assert_eq!(summary.albums_completed, 1);
+    assert_eq!(server.request_count("/api/album/1001/detail"), 0);
+    assert!(dir.path().join("002 - Second Album").exists());
+    assert!(
+        !dir.path().join("001 - First Album").exists(),
+        "numbering still covers excluded albums"
+    );
+}
+
+#[tokio::test]
+async fn song_rules_keep_track_numbers() {
+    let server = MockServer::start().await.unwrap();
+    let mut album = album_fixture(&server, "1001", "First Album", 3);
+    album.belong = Some("other".to_string());
+    server.add_album(album);
+    add_album(&server, "1002", "Second Album", 2);
+    let dir = tempfile::tempdir().unwrap();
+
+    let summary = Downloader::builder(client(&server))
+        .save_path(dir.path())
+        .retry_policy(fast_retries())
+        .filter(filter(
+            "[include]\nbelongs = [\"arknights\"]\nsong_names = [\"Song 2\"]",
+        ))
+        .build()
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    assert!(!summary.has_failures(), "{:?}", summary.failures);
+    assert_eq!(summary.tracks_completed, 1);
+    let second = dir.path().join("002 - Second Album");
+    assert!(second.join("02.Song 2.wav").exists());
+    assert!(!second.join("01.Song 1.wav").exists());
+    assert_eq!(server.request_count("/api/song/100201"), 0);
+    assert!(!dir.path().join("001 - First Album").exists());
+}
+
+#[tokio::test]
+async fn dry_run_plans_only_the_selection() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 2);
+    add_album(&server, "1002", "Second Album", 2);
+    let dir = tempfile::tempdir().unwrap();
+
+    let plan = Downloader::builder(client(&server))
+        .save_path(dir.path().join("library"))
+        .filter(filter("[exclude]\nsong_names = [\"Song 1\"]"))
+        .build()
+        .plan(&[])
+        .await
+        .unwrap();
+
+    let tracks: Vec<String> = plan
+        .downloads()
+        .filter_map(|action| match action {
+            PlannedAction::Download {
+                url,
+                kind: FileKind::Audio,
+                ..
+            } => Some(url.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        tracks,
+        [
+            server.url("/files/1002/100202.wav"),
+            server.url("/files/1001/100102.wav"),
+        ]
+    );
+    assert!(!dir.path().join("library").exists());
+    assert_eq!(server.request_count("/api/song/100101"), 0);
+}
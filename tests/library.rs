@@ -0,0 +1,138 @@
+mod common;
+
+use common::{add_album, downloader};
+use msr_downloader::mock::MockServer;
+use msr_downloader::{Album, AlbumIndex};
+use std::path::Path;
+
+fn album(cid: &str, name: &str) -> Album {
+    Album {
+        cid: cid.to_string(),
+        name: name.to_string(),
+        intro: None,
+        belong: None,
+        cover_url: None,
+        cover_de_url: None,
+        artistes: None,
+        songs: None,
+    }
+}
+
+fn create_dirs(root: &Path, dir_names: &[&str]) {
+    for dir_name in dir_names {
+        std::fs::create_dir_all(root.join(dir_name)).unwrap();
+    }
+}
+
+fn dir_names(root: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(root)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn adopts_folders_before_numbering_new_albums() {
+    let dir = tempfile::tempdir().unwrap();
+    create_dirs(dir.path(), &["001 - Second Album"]);
+    // Newest release first, as the site lists them.
+    let albums = [album("1002", "Second Album"), album("1001", "First Album")];
+
+    let mut index = AlbumIndex::load(dir.path()).await.unwrap();
+    let renames = index.update(&albums).await.unwrap();
+
+    assert!(renames.is_empty(), "{:?}", renames);
+    assert_eq!(index.get("1002").unwrap().number, 1);
+    assert_eq!(index.get("1001").unwrap().number, 2);
+    assert_eq!(index.get("1001").unwrap().dir_name, "002 - First Album");
+}
+
+#[tokio::test]
+async fn adopts_folders_of_albums_renamed_on_the_site() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    add_album(&server, "1002", "Second Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+    downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+    // Lose the index, and leave a folder from before the tool kept one.
+    std::fs::remove_file(dir.path().join(".msr-downloader/albums.json")).unwrap();
+    create_dirs(dir.path(), &["003 - Third Album (Demo)"]);
+
+    let albums = [
+        album("1003", "Third Album"),
+        album("1002", "Second Edition"),
+        album("1001", "First Album"),
+    ];
+    let mut index = AlbumIndex::load(dir.path()).await.unwrap();
+    let renames = index.update(&albums).await.unwrap();
+
+    assert_eq!(
+        renames,
+        vec![
+            (
+                "003 - Third Album (Demo)".to_string(),
+                "003 - Third Album".to_string()
+            ),
+            (
+                "002 - Second Album".to_string(),
+                "002 - Second Edition".to_string()
+            ),
+        ]
+    );
+    assert_eq!(
+        dir_names(dir.path()),
+        [
+            "001 - First Album",
+            "002 - Second Edition",
+            "003 - Third Album"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn keeps_the_current_folder_when_the_new_name_is_taken() {
+    let dir = tempfile::tempdir().unwrap();
+    create_dirs(dir.path(), &["001 - Old Name"]);
+    let mut index = AlbumIndex::load(dir.path()).await.unwrap();
+    index.update(&[album("1001", "Old Name")]).await.unwrap();
+    create_dirs(dir.path(), &["001 - New Name"]);
+
+    let renames = index.update(&[album("1001", "New Name")]).await.unwrap();
+
+    assert!(renames.is_empty(), "{:?}", renames);
+    assert_eq!(index.get("1001").unwrap().dir_name, "001 - Old Name");
+}
+
+#[tokio::test]
+async fn moves_folders_back_when_a_rename_fails() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    add_album(&server, "1002", "Second Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+    downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    // The second rename fails: the name fills the whole file name limit,
+    // leaving no room for the number.
+    let albums = [
+        album("1002", "Second Edition"),
+        album("1001", &"x".repeat(255)),
+    ];
+    let mut index = AlbumIndex::load(dir.path()).await.unwrap();
+    assert!(index.update(&albums).await.is_err());
+
+    assert_eq!(
+        dir_names(dir.path()),
+        ["001 - First Album", "002 - Second Album"]
+    );
+    let index = AlbumIndex::load(dir.path()).await.unwrap();
+    assert_eq!(index.get("1002").unwrap().dir_name, "002 - Second Album");
+}
@@ -0,0 +1,293 @@
+mod common;
+
+use common::{album_fixture, downloader};
+use lofty::config::ParseOptions;
+use lofty::file::AudioFile;
+use lofty::id3::v2::{Frame, FrameId, SynchronizedTextFrame, TimestampFormat};
+use lofty::iff::wav::WavFile;
+use lofty::prelude::*;
+use msr_downloader::lyrics::{LyricLine, LyricWord, convert_dir};
+use msr_downloader::mock::MockServer;
+use msr_downloader::{Lyrics, LyricsFormat};
+use std::borrow::Cow;
+
+fn line(time_ms: u32, text: &str) -> LyricLine {
+    LyricLine {
+        time_ms: Some(time_ms),
+        text: text.to_string(),
+        words: Vec::new(),
+    }
+}
+
+#[test]
+fn parses_timestamps_in_milliseconds() {
+    let lyrics = Lyrics::parse(
+        "\u{feff}[ti:Song 1]\r\n[ar:塞壬唱片-MSR]\r\n[00:01.5]One\r\n[00:02.25]Two\r\n\
+         [01:03.125]Three\r\n[00:04:50]Four\r\n[00:05]Five\r\n",
+    );
+
+    assert_eq!(
+        lyrics.lines,
+        [
+            line(1500, "One"),
+            line(2250, "Two"),
+            line(4500, "Four"),
+            line(5000, "Five"),
+            line(63125, "Three"),
+        ]
+    );
+    assert!(lyrics.is_synced());
+}
+
+#[test]
+fn repeats_lines_and_applies_the_offset() {
+    let lyrics =
+        Lyrics::parse("[offset:+500]\n[00:10.00][00:30.00]Chorus\n[00:20.00]Verse\n[00:40.00]\n");
+
+    assert_eq!(
+        lyrics.synced_lines().unwrap(),
+        [
+            (9500, "Chorus".to_string()),
+            (19500, "Verse".to_string()),
+            (29500, "Chorus".to_string()),
+            (39500, String::new()),
+        ]
+    );
+    assert_eq!(lyrics.text(), "Chorus\nVerse\nChorus");
+}
+
+#[test]
+fn keeps_plain_text_lyrics() {
+    let lyrics = Lyrics::parse("First line\n\n[00:01.00]Second line\n");
+
+    assert!(!lyrics.is_synced());
+    assert!(lyrics.synced_lines().is_none());
+    assert_eq!(lyrics.text(), "First line\nSecond line");
+    assert!(Lyrics::parse("[ar:Nobody]\n").is_empty());
+}
+
+#[test]
+fn keeps_id_tags_and_reports_malformed_lines() {
+    let lyrics = Lyrics::parse(
+        "[ti:Song 1]\n[AR:Artist]\n[00:01.00]One\n[00:61.00]Bad seconds\n\
+         [00:02.00\n[x-custom:value]\n[offset:soon]\n[00:03.00]Three\n",
+    );
+
+    assert_eq!(lyrics.tags.get("ti").map(String::as_str), Some("Song 1"));
+    assert_eq!(lyrics.tags.get("ar").map(String::as_str), Some("Artist"));
+    assert_eq!(lyrics.lines, [line(1000, "One"), line(3000, "Three")]);
+    let issues: Vec<String> = lyrics.issues.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        issues,
+        [
+            "line 4: invalid timestamp: [00:61.00]",
+            "line 5: unterminated tag: [00:02.00",
+            "line 6: unrecognised tag: [x-custom:value]",
+            "line 7: invalid offset: soon",
+        ]
+    );
+}
+
+#[test]
+fn reports_timestamps_out_of_range() {
+    let lyrics = Lyrics::parse(
+        "[00:01.00]One\n[99999:00.00]Too late\n[71582:47.29]Last\n\
+         [00:02.00]Two <99999:00.00>word\n",
+    );
+
+    assert_eq!(
+        lyrics.lines,
+        [line(1000, "One"), line(u32::MAX - 5, "Last")]
+    );
+    let issues: Vec<String> = lyrics.issues.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        issues,
+        [
+            "line 2: invalid timestamp: [99999:00.00]",
+            "line 4: invalid word timestamp: <99999:00.00>",
+        ]
+    );
+    // The last cue's end stops at the largest time rather than wrapping.
+    assert!(
+        lyrics
+            .export(LyricsFormat::Srt)
+            .unwrap()
+            .ends_with("1193:02:47,290 --> 1193:02:47,295\nLast\n\n")
+    );
+}
+
+#[test]
+fn parses_enhanced_word_timing() {
+    let lyrics =
+        Lyrics::parse("[offset:100]\n[00:01.00]<00:01.00>Hello <00:01.50>world<00:02.00>\n");
+
+    let word = |time_ms, text: &str| LyricWord {
+        time_ms,
+        text: text.to_string(),
+    };
+    assert_eq!(lyrics.lines[0].text, "Hello world");
+    assert_eq!(lyrics.lines[0].time_ms, Some(900));
+    assert_eq!(
+        lyrics.lines[0].words,
+        [word(900, "Hello "), word(1400, "world")]
+    );
+    assert!(lyrics.issues.is_empty());
+}
+
+#[test]
+fn decodes_legacy_encodings() {
+    let content = "[00:01.00]第一行\n";
+
+    let (gb18030, _, _) = encoding_rs::GB18030.encode(content);
+    assert_eq!(Lyrics::from_bytes(&gb18030).lines, [line(1000, "第一行")]);
+
+    let mut utf16 = vec![0xff, 0xfe];
+    utf16.extend(content.encode_utf16().flat_map(u16::to_le_bytes));
+    assert_eq!(Lyrics::from_bytes(&utf16).lines, [line(1000, "第一行")]);
+}
+
+#[test]
+fn exports_subtitle_formats() {
+    let lyrics = Lyrics::parse(
+        "[la:en]\n[length:00:09.50]\n[00:01.00][00:05.00]One & two\n[00:01.00]Second voice\n\
+         [00:03.00]\n[00:05.00]<00:05.00>Three <00:06.00><four>\n",
+    );
+
+    assert_eq!(
+        lyrics.export(LyricsFormat::Srt).unwrap(),
+        "1\n00:00:01,000 --> 00:00:03,000\nOne & two\nSecond voice\n\n\
+         2\n00:00:05,000 --> 00:00:09,500\nOne & two\nThree <four>\n\n"
+    );
+    assert_eq!(
+        lyrics.export(LyricsFormat::WebVtt).unwrap(),
+        "WEBVTT\n\n00:00:01.000 --> 00:00:03.000\nOne &amp; two\nSecond voice\n\n\
+         00:00:05.000 --> 00:00:09.500\nOne &amp; two\nThree <00:00:06.000>&lt;four&gt;\n\n"
+    );
+    let ttml = lyrics.export(LyricsFormat::Ttml).unwrap();
+    assert!(ttml.contains("<tt xmlns=\"http://www.w3.org/ns/ttml\" xml:lang=\"en\">"));
+    assert!(ttml.contains(
+        "<p begin=\"00:00:01.000\" end=\"00:00:03.000\">One &amp; two<br/>Second voice</p>"
+    ));
+    assert!(ttml.contains(
+        "<span begin=\"00:00:05.000\" end=\"00:00:06.000\">Three </span>\
+         <span begin=\"00:00:06.000\" end=\"00:00:09.500\">&lt;four&gt;</span>"
+    ));
+
+    assert_eq!(
+        lyrics.export(LyricsFormat::Text).unwrap(),
+        "One & two\nSecond voice\nOne & two\nThree <four>\n"
+    );
+    assert!(
+        Lyrics::parse("Unsynced\n")
+            .export(LyricsFormat::Srt)
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn converts_a_directory_of_lyrics() {
+    let dir = tempfile::tempdir().unwrap();
+    let album = dir.path().join("001 - First Album");
+    tokio::fs::create_dir_all(&album).await.unwrap();
+    tokio::fs::write(album.join("01.Song 1.lrc"), "[00:01.00]One\n[bad\n")
+        .await
+        .unwrap();
+    tokio::fs::write(album.join("02.Song 2.lrc"), "Unsynced\n")
+        .await
+        .unwrap();
+    tokio::fs::write(album.join("01.Song 1.srt"), "old")
+        .await
+        .unwrap();
+
+    let formats = [LyricsFormat::Srt, LyricsFormat::WebVtt];
+    let summary = convert_dir(dir.path(), &formats, false).await.unwrap();
+
+    assert_eq!(summary.converted, 1);
+    assert_eq!(summary.skipped, 1);
+    assert_eq!(summary.failed.len(), 2);
+    assert_eq!(summary.issues.len(), 1);
+    assert_eq!(summary.issues[0].0, album.join("01.Song 1.lrc"));
+    assert_eq!(
+        tokio::fs::read_to_string(album.join("01.Song 1.srt"))
+            .await
+            .unwrap(),
+        "old"
+    );
+    assert!(album.join("01.Song 1.vtt").exists());
+
+    let summary = convert_dir(dir.path(), &formats, true).await.unwrap();
+    assert_eq!((summary.converted, summary.skipped), (2, 0));
+    assert!(
+        tokio::fs::read_to_string(album.join("01.Song 1.srt"))
+            .await
+            .unwrap()
+            .starts_with("1\n00:00:01,000 --> 00:00:06,000\nOne\n")
+    );
+}
+
+#[tokio::test]
+async fn keeps_converting_after_a_failed_write() {
+    let dir = tempfile::tempdir().unwrap();
+    tokio::fs::write(dir.path().join("01.Song 1.lrc"), "[00:01.00]One\n")
+        .await
+        .unwrap();
+    tokio::fs::write(dir.path().join("02.Song 2.lrc"), "[00:01.00]Two\n")
+        .await
+        .unwrap();
+    // A directory where the output should go makes the write fail.
+    tokio::fs::create_dir(dir.path().join("01.Song 1.srt"))
+        .await
+        .unwrap();
+
+    let summary = convert_dir(dir.path(), &[LyricsFormat::Srt], true)
+        .await
+        .unwrap();
+
+    assert_eq!(summary.converted, 1);
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].0, dir.path().join("01.Song 1.srt"));
+    assert!(dir.path().join("02.Song 2.srt").exists());
+}
+
+#[tokio::test]
+async fn embeds_lyrics_into_tags() {
+    let server = MockServer::start().await.unwrap();
+    let album = album_fixture(&server, "1001", "First Album", 1);
+    server.add_file(
+        "/files/1001/100101.lrc",
+        "[00:00.50]First line\n[00:01.75]第二行\n",
+    );
+    server.add_album(album);
+    let dir = tempfile::tempdir().unwrap();
+
+    let summary = downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+    assert!(!summary.has_failures(), "{:?}", summary.failures);
+
+    let track = dir.path().join("001 - First Album/01.Song 1.wav");
+    let tagged = lofty::read_from_path(&track).unwrap();
+    let tag = tagged.primary_tag().unwrap();
+    assert_eq!(tag.get_string(&ItemKey::Lyrics), Some("First line\n第二行"));
+
+    let mut file = std::fs::File::open(&track).unwrap();
+    let wav = WavFile::read_from(&mut file, ParseOptions::new()).unwrap();
+    let frame = wav
+        .id3v2()
+        .unwrap()
+        .get(&FrameId::Valid(Cow::Borrowed("SYLT")))
+        .expect("SYLT frame written");
+    let Frame::Binary(binary) = frame else {
+        panic!("unexpected SYLT frame {:?}", frame);
+    };
+    let sylt = SynchronizedTextFrame::parse(&binary.data, binary.flags()).unwrap();
+    assert_eq!(sylt.timestamp_format, TimestampFormat::MS);
+    assert_eq!(
+        sylt.content,
+        [
+            (500, "First line".to_string()),
+            (1750, "第二行".to_string())
+        ]
+    );
+}
@@ -0,0 +1,257 @@
+mod common;
+
+use common::{add_album, album_fixture, client, downloader, fast_retries};
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::file::AudioFile;
+use lofty::iff::wav::WavFile;
+use lofty::prelude::*;
+use msr_downloader::metadata::{ALBUM_ID_TAG, SONG_ID_TAG};
+use msr_downloader::mock::MockServer;
+use msr_downloader::{Config, Downloader, GenreMap, GenreMapping, TagPolicies, TagPolicy};
+use std::path::Path;
+
+async fn retag(server: &MockServer, save_path: &Path, policies: TagPolicies, force: bool) {
+    let summary = Downloader::builder(client(server))
+        .save_path(save_path)
+        .retry_policy(fast_retries())
+        .tag_policies(policies)
+        .force_retag(force)
+        .build()
+        .download_all_tracks()
+        .await
+        .unwrap();
+    assert!(!summary.has_failures(), "{:?}", summary.failures);
+}
+
+/// Stands in for a player editing the tags: renames the track, rewrites
+/// the comment and adds a ReplayGain value.
+fn edit_tags(track: &Path) {
+    let mut tagged = lofty::read_from_path(track).unwrap();
+    let tag = tagged.primary_tag_mut().unwrap();
+    tag.set_title("My Title".to_string());
+    tag.set_comment("My notes".to_string());
+    tag.insert_text(ItemKey::ReplayGainTrackGain, "-6.50 dB".to_string());
+    tagged.save_to_path(track, WriteOptions::default()).unwrap();
+}
+
+#[tokio::test]
+async fn merges_into_existing_tags() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+    downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+    let track = dir.path().join("001 - First Album/01.Song 1.wav");
+    edit_tags(&track);
+
+    let policies = TagPolicies {
+        title: TagPolicy::Never,
+        comment: TagPolicy::OnlyIfEmpty,
+        ..TagPolicies::default()
+    };
+    retag(&server, dir.path(), policies, false).await;
+
+    let tagged = lofty::read_from_path(&track).unwrap();
+    let tag = tagged.primary_tag().unwrap();
+    assert_eq!(tag.title().as_deref(), Some("My Title"));
+    assert_eq!(tag.comment().as_deref(), Some("My notes"));
+    assert_eq!(tag.album().as_deref(), Some("First Album"));
+    assert_eq!(tag.track(), Some(1));
+    assert_eq!(
+        tag.get_string(&ItemKey::ReplayGainTrackGain),
+        Some("-6.50 dB")
+    );
+    assert_eq!(tag.pictures().len(), 1);
+
+    // Re-tagging replaces the tool's own frames rather than adding more.
+    let mut file = std::fs::File::open(&track).unwrap();
+    let wav = WavFile::read_from(&mut file, ParseOptions::new()).unwrap();
+    let sylt_frames = wav
+        .id3v2()
+        .unwrap()
+        .into_iter()
+        .filter(|frame| frame.id_str() == "SYLT")
+        .count();
+    assert_eq!(sylt_frames, 1);
+}
+
+#[tokio::test]
+async fn force_retag_replaces_existing_tags() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+    downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+    let track = dir.path().join("001 - First Album/01.Song 1.wav");
+    edit_tags(&track);
+
+    let policies = TagPolicies {
+        title: TagPolicy::Never,
+        ..TagPolicies::default()
+    };
+    retag(&server, dir.path(), policies, true).await;
+
+    let tagged = lofty::read_from_path(&track).unwrap();
+    let tag = tagged.primary_tag().unwrap();
+    assert_eq!(tag.title().as_deref(), Some("Song 1"));
+    assert_eq!(tag.comment().as_deref(), Some("Intro of First Album"));
+    assert_eq!(tag.get_string(&ItemKey::ReplayGainTrackGain), None);
+}
+
+#[tokio::test]
+async fn tags_album_artist_disc_and_catalog_ids() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+    downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    let track = dir.path().join("001 - First Album/01.Song 1.wav");
+    let tagged = lofty::read_from_path(&track).unwrap();
+    let tag = tagged.primary_tag().unwrap();
+    assert_eq!(tag.get_string(&ItemKey::AlbumArtist), Some("塞壬唱片-MSR"));
+    assert_eq!((tag.disk(), tag.disk_total()), (Some(1), Some(1)));
+    assert_eq!(
+        tag.get_string(&ItemKey::Unknown(ALBUM_ID_TAG.to_string())),
+        Some("1001")
+    );
+    assert_eq!(
+        tag.get_string(&ItemKey::Unknown(SONG_ID_TAG.to_string())),
+        Some("100101")
+    );
+    let source_url = server.url("/files/1001/100101.wav");
+    assert_eq!(
+        tag.get(&ItemKey::AudioSourceUrl)
+            .and_then(|item| item.value().locator()),
+        Some(source_url.as_str())
+    );
+}
+
+#[tokio::test]
+async fn maps_belong_to_genre_from_the_config() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    let mut second = album_fixture(&server, "1002", "Second Album", 1);
+    second.belong = Some("Endfield".to_string());
+    server.add_album(second);
+    let mut third = album_fixture(&server, "1003", "Third Album", 1);
+    third.belong = Some("side-story".to_string());
+    server.add_album(third);
+    let dir = tempfile::tempdir().unwrap();
+
+    let config_path = dir.path().join("msr-downloader.toml");
+    std::fs::write(
+        &config_path,
+        "[genres.belongs.endfield]\n\
+         genre = \"Game Soundtrack\"\n\
+         grouping = \"Arknights: Endfield\"\n\
+         \n\
+         [genres.belongs.arknights]\n\
+         grouping = \"Arknights\"\n",
+    )
+    .unwrap();
+    let config = Config::load(&config_path).await.unwrap();
+    let library = dir.path().join("library");
+    let summary = Downloader::builder(client(&server))
+        .save_path(&library)
+        .retry_policy(fast_retries())
+        .genres(config.genres)
+        .build()
+        .download_all_tracks()
+        .await
+        .unwrap();
+    assert!(!summary.has_failures(), "{:?}", summary.failures);
+
+    let tags = |album: &str| {
+        let track = library.join(album).join("01.Song 1.wav");
+        let tagged = lofty::read_from_path(track).unwrap();
+        let tag = tagged.primary_tag().unwrap();
+        (
+            tag.genre().map(|genre| genre.into_owned()),
+            tag.get_string(&ItemKey::ContentGroup).map(str::to_string),
+        )
+    };
+    assert_eq!(
+        tags("001 - First Album"),
+        (None, Some("Arknights".to_string()))
+    );
+    assert_eq!(
+        tags("002 - Second Album"),
+        (
+            Some("Game Soundtrack".to_string()),
+            Some("Arknights: Endfield".to_string())
+        )
+    );
+    assert_eq!(tags("003 - Third Album"), (None, None));
+}
+
+#[test]
+fn maps_known_belongs_by_default() {
+    let genres = GenreMap::default();
+
+    assert_eq!(
+        genres.get(Some("Arknights")).genre.as_deref(),
+        Some("Arknights")
+    );
+    assert_eq!(
+        genres.get(Some("endfield")).grouping.as_deref(),
+        Some("Arknights: Endfield")
+    );
+    assert_eq!(
+        genres.get(Some("popucom")).genre.as_deref(),
+        Some("POPUCOM")
+    );
+    assert_eq!(genres.get(Some("unknown")), GenreMapping::default());
+    assert_eq!(genres.get(None), GenreMapping::default());
+}
+
+#[tokio::test]
+async fn removes_genres_the_mapping_no_longer_sets() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+    downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+    let track = dir.path().join("001 - First Album/01.Song 1.wav");
+    let genre = |track: &Path| {
+        let tagged = lofty::read_from_path(track).unwrap();
+        let tag = tagged.primary_tag().unwrap();
+        (
+            tag.genre().map(|genre| genre.into_owned()),
+            tag.get_string(&ItemKey::ContentGroup).map(str::to_string),
+        )
+    };
+    assert_eq!(
+        genre(&track),
+        (Some("Arknights".to_string()), Some("Arknights".to_string()))
+    );
+
+    let genres = GenreMap {
+        belongs: [("arknights".to_string(), GenreMapping::default())].into(),
+        ..GenreMap::default()
+    };
+    // Only-if-empty keeps the grouping that is already there.
+    let policies = TagPolicies {
+        grouping: TagPolicy::OnlyIfEmpty,
+        ..TagPolicies::default()
+    };
+    Downloader::builder(client(&server))
+        .save_path(dir.path())
+        .retry_policy(fast_retries())
+        .tag_policies(policies)
+        .genres(genres)
+        .build()
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    assert_eq!(genre(&track), (None, Some("Arknights".to_string())));
+}
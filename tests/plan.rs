@@ -0,0 +1,104 @@
+mod common;
+
+use common::{add_album, downloader, wav_bytes};
+use msr_downloader::mock::MockServer;
+use msr_downloader::{FileKind, PlannedAction};
+
+#[tokio::test]
+async fn plans_a_fresh_download_without_writing() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 2);
+    let dir = tempfile::tempdir().unwrap();
+    let library = dir.path().join("library");
+
+    let plan = downloader(&server, &library).plan(&[]).await.unwrap();
+
+    let album = library.join("001 - First Album");
+    let actions: Vec<(&str, _)> = plan
+        .actions
+        .iter()
+        .map(|action| (action.as_str(), action.path().to_path_buf()))
+        .collect();
+    assert_eq!(
+        actions,
+        [
+            ("create_dir", library.clone()),
+            ("create_dir", album.clone()),
+            ("write_info", album.join("info.txt")),
+            ("download", album.join("Album Cover.jpg")),
+            ("download", album.join("01.Song 1.wav")),
+            ("download", album.join("01.Song 1.lrc")),
+            ("download", album.join("02.Song 2.wav")),
+            ("download", album.join("02.Song 2.lrc")),
+            ("tag", album.join("01.Song 1.wav")),
+            ("tag", album.join("02.Song 2.wav")),
+        ]
+    );
+
+    let PlannedAction::Download {
+        url, kind, size, ..
+    } = &plan.actions[4]
+    else {
+        panic!("expected a download, got {:?}", plan.actions[4]);
+    };
+    assert_eq!(url, &server.url("/files/1001/100101.wav"));
+    assert_eq!(*kind, FileKind::Audio);
+    assert_eq!(*size, Some(wav_bytes(4000).len() as u64));
+    assert!(
+        plan.downloads()
+            .all(|action| matches!(action, PlannedAction::Download { size: Some(_), .. }))
+    );
+
+    assert!(!library.exists());
+}
+
+#[tokio::test]
+async fn plans_only_what_is_missing() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+    downloader(&server, dir.path())
+        .download_all_tracks()
+        .await
+        .unwrap();
+
+    let renamed = MockServer::start().await.unwrap();
+    add_album(&renamed, "1001", "First Album (Deluxe)", 1);
+    add_album(&renamed, "1002", "Second Album", 1);
+    tokio::fs::remove_file(dir.path().join("001 - First Album/01.Song 1.lrc"))
+        .await
+        .unwrap();
+
+    let plan = downloader(&renamed, dir.path()).plan(&[]).await.unwrap();
+
+    let second = dir.path().join("002 - Second Album");
+    let first = dir.path().join("001 - First Album (Deluxe)");
+    assert_eq!(
+        plan.actions[0],
+        PlannedAction::Rename {
+            from: dir.path().join("001 - First Album"),
+            to: first.clone(),
+        }
+    );
+    let actions: Vec<(&str, _)> = plan.actions[1..]
+        .iter()
+        .map(|action| (action.as_str(), action.path().to_path_buf()))
+        .collect();
+    assert_eq!(
+        actions,
+        [
+            ("create_dir", second.clone()),
+            ("write_info", second.join("info.txt")),
+            ("download", second.join("Album Cover.jpg")),
+            ("download", second.join("01.Song 1.wav")),
+            ("download", second.join("01.Song 1.lrc")),
+            ("tag", second.join("01.Song 1.wav")),
+            ("download", first.join("01.Song 1.lrc")),
+            ("tag", first.join("01.Song 1.wav")),
+        ]
+    );
+
+    assert!(dir.path().join("001 - First Album").exists());
+    assert!(!first.exists());
+    assert!(!second.exists());
+}
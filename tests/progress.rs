@@ -0,0 +1,34 @@
+use msr_downloader::progress::ProgressTracker;
+
+#[test]
+fn bounds_the_number_of_file_bars() {
+    let progress = ProgressTracker::with_max_download_bars(2);
+
+    let first = progress.start_download("01.Song 1.wav", 0, Some(10));
+    let second = progress.start_download("02.Song 2.wav", 0, None);
+    let third = progress.start_download("03.Song 3.wav", 0, Some(10));
+    assert!(first.has_bar());
+    assert!(second.has_bar());
+    assert!(!third.has_bar());
+
+    drop(first);
+    assert!(progress.start_download("04.Song 4.wav", 0, None).has_bar());
+}
+
+#[test]
+fn totals_only_finished_downloads() {
+    let progress = ProgressTracker::with_max_download_bars(1);
+
+    let mut finished = progress.start_download("01.Song 1.wav", 4, Some(6));
+    finished.inc(6);
+    finished.finish();
+
+    let mut failed = progress.start_download("02.Song 2.wav", 0, Some(10));
+    failed.inc(3);
+    assert_eq!(progress.downloaded_bytes(), 9);
+    drop(failed);
+    assert_eq!(progress.downloaded_bytes(), 6);
+
+    progress.finish_downloads();
+    assert_eq!(progress.downloaded_bytes(), 0);
+}
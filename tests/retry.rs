@@ -0,0 +1,105 @@
+mod common;
+
+use common::{client, fast_retries};
+use msr_downloader::mock::{Fault, MockServer};
+use msr_downloader::{Error, Result, utils};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Serves `bodies` in turn, each claiming to be `content_length` bytes
+/// long, and returns the URL along with the number of requests served.
+async fn serve(bodies: Vec<&'static str>, content_length: usize) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/api/albums", listener.local_addr().unwrap());
+    let served = Arc::new(AtomicUsize::new(0));
+
+    let counter = served.clone();
+    tokio::spawn(async move {
+        for body in bodies {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n",
+                content_length
+            );
+            let _ = stream.write_all(head.as_bytes()).await;
+            let _ = stream.write_all(body.as_bytes()).await;
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+
+    (url, served)
+}
+
+async fn fetch_json(url: &str) -> Result<serde_json::Value> {
+    let client = reqwest::Client::new();
+    fast_retries()
+        .run(|| async { Ok(client.get(url).send().await?.json().await?) })
+        .await
+}
+
+#[tokio::test]
+async fn retries_bodies_cut_off_mid_transfer() {
+    let body = r#"{"code":0,"msg":"","data":[]}"#;
+    let (url, served) = serve(vec![&body[..10], body], body.len()).await;
+
+    let value = fetch_json(&url).await.unwrap();
+
+    assert_eq!(value["code"], 0);
+    assert_eq!(served.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn does_not_retry_malformed_json() {
+    let body = "not json";
+    let (url, served) = serve(vec![body, body], body.len()).await;
+
+    let error = fetch_json(&url).await.unwrap_err();
+
+    assert!(
+        matches!(error, Error::Http(ref e) if e.is_decode()),
+        "{:?}",
+        error
+    );
+    assert_eq!(error.attempts(), 1);
+    assert_eq!(served.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn caps_retry_after_at_max_backoff() {
+    let server = MockServer::start().await.unwrap();
+    server.inject_fault("/api/albums", Fault::StatusRetryAfter(503, 3600), 1);
+
+    let started = Instant::now();
+    let albums = client(&server).get_albums().await.unwrap();
+
+    assert!(albums.is_empty());
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(server.request_count("/api/albums"), 2);
+}
+
+#[test]
+fn parses_retry_after_http_dates() {
+    assert_eq!(
+        utils::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+        Some(784_111_777)
+    );
+    assert_eq!(
+        utils::parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"),
+        Some(0)
+    );
+    assert_eq!(
+        utils::parse_http_date("Thu, 29 Feb 2024 23:59:59 GMT"),
+        Some(1_709_251_199)
+    );
+    assert_eq!(
+        utils::parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"),
+        None
+    );
+    assert_eq!(utils::parse_http_date("120"), None);
+}
@@ -0,0 +1,249 @@
+mod common;
+
+use bytes::Bytes;
+use common::{add_album, album_fixture, fast_retries};
+use futures::TryStreamExt;
+use lofty::prelude::*;
+use msr_downloader::mock::MockServer;
+use msr_downloader::{
+    Album, CatalogDiff, CatalogSnapshot, CatalogSource, Downloader, FileResponse, FixtureSource,
+    MonsterSirenClient, Result, ResumeFrom, SnapshotSource, Song,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Wraps a fixture to record the most detail requests in flight at once.
+#[derive(Default)]
+struct CountingSource {
+    inner: FixtureSource,
+    in_flight: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl CountingSource {
+    async fn track<T>(&self, request: impl Future<Output = T>) -> T {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let result = request.await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+}
+
+impl CatalogSource for CountingSource {
+    async fn get_albums(&self) -> Result<Vec<Album>> {
+        self.inner.get_albums().await
+    }
+
+    async fn get_album_with_songs(&self, album_id: &str) -> Result<Option<Album>> {
+        self.track(self.inner.get_album_with_songs(album_id)).await
+    }
+
+    async fn get_song(&self, song_id: &str) -> Result<Option<Song>> {
+        self.track(self.inner.get_song(song_id)).await
+    }
+
+    async fn get_songs(&self) -> Result<Vec<Song>> {
+        self.inner.get_songs().await
+    }
+
+    async fn fetch_file(&self, url: &str, resume: Option<ResumeFrom<'_>>) -> Result<FileResponse> {
+        self.inner.fetch_file(url, resume).await
+    }
+
+    async fn file_size(&self, url: &str) -> Result<Option<u64>> {
+        self.inner.file_size(url).await
+    }
+}
+
+#[tokio::test]
+async fn snapshot_captures_song_details() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 2);
+    let client = MonsterSirenClient::builder()
+        .base_url(server.url(""))
+        .retry_policy(fast_retries())
+        .build()
+        .unwrap();
+
+    let snapshot = CatalogSnapshot::fetch(&client, 4).await.unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("catalog.json");
+    snapshot.save(&path).await.unwrap();
+    let loaded = CatalogSnapshot::load(&path).await.unwrap();
+
+    assert_eq!(loaded.albums.len(), 1);
+    let songs = loaded.albums[0].get_songs();
+    assert_eq!(songs.len(), 2);
+    assert_eq!(songs[0].cid, "100101");
+    assert!(songs[0].source_url.is_some());
+    assert!(songs[1].lyric_url.is_some());
+}
+
+#[tokio::test]
+async fn diff_reports_catalog_changes() {
+    let server = MockServer::start().await.unwrap();
+    let first = add_album(&server, "1001", "First Album", 2);
+    add_album(&server, "1002", "Second Album", 1);
+    let client = MonsterSirenClient::builder()
+        .base_url(server.url(""))
+        .retry_policy(fast_retries())
+        .build()
+        .unwrap();
+    let old = CatalogSnapshot::fetch(&client, 4).await.unwrap();
+
+    let mut renamed = first.get_songs()[0].clone();
+    renamed.name = "Song 1 (Remastered)".to_string();
+    renamed.source_url = Some(server.url("/files/1001/100101-remaster.wav"));
+    server.update_song(renamed);
+    let mut recredited = first.get_songs()[1].clone();
+    recredited.artists = Some(vec!["Guest Artist".to_string()]);
+    server.update_song(recredited);
+    add_album(&server, "1003", "Third Album", 1);
+
+    let mut new = CatalogSnapshot::fetch(&client, 4).await.unwrap();
+    new.albums.retain(|album| album.cid != "1002");
+
+    let diff = CatalogDiff::new(&old, &new);
+    let cids = |items: &[msr_downloader::diff::CatalogItem]| {
+        items
+            .iter()
+            .map(|item| item.cid.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(cids(&diff.added_albums), ["1003"]);
+    assert_eq!(cids(&diff.removed_albums), ["1002"]);
+    assert_eq!(cids(&diff.added_songs), ["100301"]);
+    assert_eq!(cids(&diff.removed_songs), ["100201"]);
+
+    assert_eq!(diff.renamed_songs.len(), 1);
+    assert_eq!(diff.renamed_songs[0].new_name, "Song 1 (Remastered)");
+    assert_eq!(diff.changed_source_urls.len(), 1);
+    assert_eq!(diff.changed_source_urls[0].song_cid, "100101");
+    assert_eq!(diff.changed_artists.len(), 1);
+    assert_eq!(diff.changed_artists[0].song_cid.as_deref(), Some("100102"));
+    assert_eq!(diff.changed_artists[0].new_artists, ["Guest Artist"]);
+
+    assert!(CatalogDiff::new(&new, &new).is_empty());
+}
+
+#[tokio::test]
+async fn snapshot_limits_requests_in_flight() {
+    let server = MockServer::start().await.unwrap();
+    let mut source = CountingSource::default();
+    for (cid, tracks) in [("1003", 1), ("1002", 4), ("1001", 3)] {
+        let album = album_fixture(&server, cid, &format!("Album {}", cid), tracks);
+        source.inner = source.inner.with_album(album);
+    }
+
+    let snapshot = CatalogSnapshot::fetch(&source, 2).await.unwrap();
+
+    assert_eq!(source.peak.load(Ordering::SeqCst), 2);
+    let cids: Vec<Vec<String>> = snapshot
+        .albums
+        .iter()
+        .map(|album| album.get_songs().into_iter().map(|song| song.cid).collect())
+        .collect();
+    assert_eq!(
+        cids,
+        [
+            vec!["100301"],
+            vec!["100201", "100202", "100203", "100204"],
+            vec!["100101", "100102", "100103"],
+        ]
+    );
+    assert!(
+        snapshot
+            .albums
+            .iter()
+            .flat_map(Album::get_songs)
+            .all(|song| song.source_url.is_some())
+    );
+}
+
+#[tokio::test]
+async fn downloads_from_a_snapshot_directory() {
+    let server = MockServer::start().await.unwrap();
+    add_album(&server, "1001", "First Album", 2);
+    add_album(&server, "1002", "Second Album", 1);
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("snapshot");
+    let client = MonsterSirenClient::builder()
+        .base_url(server.url(""))
+        .retry_policy(fast_retries())
+        .build()
+        .unwrap();
+
+    let snapshot = SnapshotSource::create(&client, &root, 2).await.unwrap();
+    assert_eq!(snapshot.albums.len(), 2);
+    let audio_url = server.url("/files/1001/100101.wav");
+    let audio_path = SnapshotSource::file_path(&root, &audio_url).unwrap();
+    assert!(audio_path.ends_with("files/127.0.0.1/files/1001/100101.wav"));
+    assert!(
+        SnapshotSource::file_path(&root, "http://example.com/a:b/c*d.wav")
+            .unwrap()
+            .ends_with("files/example.com/a_b/c_d.wav")
+    );
+
+    // Serve the snapshot with the site gone.
+    drop(server);
+    let source = SnapshotSource::open(&root).await.unwrap();
+    let albums = source.get_albums().await.unwrap();
+    assert_eq!(
+        albums
+            .iter()
+            .map(|album| album.cid.as_str())
+            .collect::<Vec<_>>(),
+        ["1002", "1001"]
+    );
+    assert!(albums[0].songs.is_none());
+    let album = source.get_album_with_songs("1001").await.unwrap().unwrap();
+    assert_eq!(album.get_songs().len(), 2);
+    let song = source.get_song("100102").await.unwrap().unwrap();
+    assert_eq!(song.name, "Song 2");
+    assert!(source.get_song("999999").await.unwrap().is_none());
+    assert_eq!(source.get_songs().await.unwrap().len(), 3);
+
+    let audio = std::fs::read(&audio_path).unwrap();
+    assert_eq!(
+        source.file_size(&audio_url).await.unwrap(),
+        Some(audio.len() as u64)
+    );
+    let whole = source.fetch_file(&audio_url, None).await.unwrap();
+    assert_eq!(whole.partial_from, None);
+    let etag = whole.etag.unwrap();
+    let resume = ResumeFrom {
+        offset: 100,
+        validator: &etag,
+    };
+    let partial = source.fetch_file(&audio_url, Some(resume)).await.unwrap();
+    assert_eq!(partial.partial_from, Some(100));
+    let body: Vec<Bytes> = partial.body.try_collect().await.unwrap();
+    assert_eq!(body.concat(), audio[100..]);
+    let stale = ResumeFrom {
+        offset: 100,
+        validator: "\"stale\"",
+    };
+    let restarted = source.fetch_file(&audio_url, Some(stale)).await.unwrap();
+    assert_eq!(restarted.partial_from, None);
+
+    let library = dir.path().join("library");
+    let summary = Downloader::builder(source)
+        .save_path(&library)
+        .retry_policy(fast_retries())
+        .build()
+        .download_all_tracks()
+        .await
+        .unwrap();
+    assert!(!summary.has_failures(), "{:?}", summary.failures);
+    let track = library.join("001 - First Album/01.Song 1.wav");
+    assert_eq!(
+        lofty::read_from_path(track)
+            .unwrap()
+            .primary_tag()
+            .and_then(|tag| tag.title().map(|title| title.into_owned())),
+        Some("Song 1".to_string())
+    );
+    assert!(library.join("002 - Second Album/01.Song 1.lrc").exists());
+}